@@ -1,51 +1,143 @@
-use reqwest::StatusCode;
+use reqwest::{Method, StatusCode};
 
 pub struct Client {
     api_base_url: String,
 }
 
+// percent-encode a path segment, so that keys and members may hold any character,
+// slashes included
+pub fn encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// split a list of values, each one sent as its length in bytes on a line followed by
+// its bytes and a line feed
+fn parse_values(mut body: &[u8]) -> Option<Vec<String>> {
+    let mut values = vec![];
+    while !body.is_empty() {
+        let newline = body.iter().position(|&b| b == b'\n')?;
+        let len: usize = std::str::from_utf8(&body[..newline]).ok()?.parse().ok()?;
+        let value = body.get(newline + 1..newline + 1 + len)?;
+        values.push(String::from_utf8_lossy(value).to_string());
+        body = body.get(newline + 2 + len..)?;
+    }
+    Some(values)
+}
+
 impl Client {
     pub fn new(api_base_url: String) -> Self {
         Self { api_base_url }
     }
 
     pub async fn get_string(&self, key: &str) -> Option<String> {
-        let api_url = format!("{}/{}", self.api_base_url, key);
+        let api_url = format!("{}/{}", self.api_base_url, encode(key));
         let result = reqwest::get(api_url).await;
         if result.is_err() {
             return None;
         }
         let resp = result.unwrap();
         match resp.status() {
-            StatusCode::OK => {
-                if let Ok(val) = resp.text().await {
-                    Some(val)
-                } else {
-                    None
-                }
-            }
+            StatusCode::OK => resp.text().await.ok(),
             _ => None,
         }
     }
 
     pub async fn put_string(&self, key: &str, value: &str) -> Option<String> {
-        let api_url = format!("{}/{}/{}", self.api_base_url, key, value);
+        let api_url = format!("{}/{}", self.api_base_url, encode(key));
         let cli = reqwest::Client::new();
-        let result = cli.put(api_url).send().await;
+        let result = cli.put(api_url).body(value.to_string()).send().await;
         if result.is_err() {
             println!("{:?}", result);
             return None;
         }
         let resp = result.unwrap();
         match resp.status() {
-            StatusCode::OK => {
-                if let Ok(val) = resp.text().await {
-                    Some(val)
-                } else {
-                    None
+            StatusCode::OK => resp.text().await.ok(),
+            _ => None,
+        }
+    }
+
+    // send a request to the given path, return the response text if it succeeded,
+    // otherwise the error message of the server
+    pub async fn request(&self, method: Method, path: &str) -> Result<String, String> {
+        self.request_with_body(method, path, String::new()).await
+    }
+
+    pub async fn request_with_body(
+        &self,
+        method: Method,
+        path: &str,
+        body: String,
+    ) -> Result<String, String> {
+        let bytes = self.send(method, path, body).await?;
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    // send a request whose response is a list of values, and return them
+    pub async fn request_values(&self, method: Method, path: &str) -> Result<Vec<String>, String> {
+        let bytes = self.send(method, path, String::new()).await?;
+        parse_values(&bytes).ok_or_else(|| "malformed list of values".to_string())
+    }
+
+    async fn send(&self, method: Method, path: &str, body: String) -> Result<Vec<u8>, String> {
+        let api_url = format!("{}/{}", self.api_base_url, path);
+        let cli = reqwest::Client::new();
+        let resp = cli
+            .request(method, api_url)
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = resp.status();
+        let bytes = resp.bytes().await.map_err(|err| err.to_string())?;
+        let text = String::from_utf8_lossy(&bytes).to_string();
+        match status {
+            _ if status.is_success() => Ok(bytes.to_vec()),
+            StatusCode::NOT_FOUND if text.is_empty() => Err("(nil)".to_string()),
+            _ if text.is_empty() => Err(status.to_string()),
+            _ => Err(text),
+        }
+    }
+
+    // read the server-sent events of the path until the server closes the connection,
+    // f is called with the name and the data lines of each event
+    pub async fn events<F>(&self, path: &str, mut f: F) -> Result<(), String>
+    where
+        F: FnMut(&str, &[&str]),
+    {
+        let api_url = format!("{}/{}", self.api_base_url, path);
+        let mut resp = reqwest::get(api_url).await.map_err(|err| err.to_string())?;
+        if !resp.status().is_success() {
+            return Err(resp.status().to_string());
+        }
+        let mut buffer = String::new();
+        while let Some(chunk) = resp.chunk().await.map_err(|err| err.to_string())? {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = buffer.find("\n\n") {
+                let event: String = buffer.drain(..end + 2).collect();
+                let mut name = "message";
+                let mut data = vec![];
+                for line in event.lines() {
+                    if let Some(value) = line.strip_prefix("event: ") {
+                        name = value;
+                    } else if let Some(value) = line.strip_prefix("data: ") {
+                        data.push(value);
+                    }
+                }
+                // comments only keep the connection alive
+                if !data.is_empty() {
+                    f(name, &data);
                 }
             }
-            _ => None,
         }
+        Ok(())
     }
 }
@@ -0,0 +1,79 @@
+use reqwest::Method;
+
+use crate::client::{encode, Client};
+
+pub const COMMANDS: [&str; 7] = [
+    "hset", "hget", "hdel", "hgetall", "hincrby", "hexists", "hlen",
+];
+
+fn usage(op: &str) -> &'static str {
+    match op {
+        "hset" => "hset <key> <field> <value>",
+        "hget" => "hget <key> <field>",
+        "hdel" => "hdel <key> <field>",
+        "hgetall" => "hgetall <key>",
+        "hincrby" => "hincrby <key> <field> <delta>",
+        "hexists" => "hexists <key> <field>",
+        _ => "hlen <key>",
+    }
+}
+
+pub async fn handle_cmd(cli: &Client, op: &str, args: &[&str]) {
+    let mut body = String::new();
+    let (method, path) = match (op, args) {
+        // the value goes in the body
+        ("hset", [key, field, value]) => {
+            body = value.to_string();
+            (
+                Method::PUT,
+                format!("hash/{}/{}", encode(key), encode(field)),
+            )
+        }
+        ("hget", [key, field]) => (
+            Method::GET,
+            format!("hash/{}/{}", encode(key), encode(field)),
+        ),
+        ("hdel", [key, field]) => (
+            Method::DELETE,
+            format!("hash/{}/{}", encode(key), encode(field)),
+        ),
+        ("hgetall", [key]) => (Method::GET, format!("hash/{}", encode(key))),
+        ("hincrby", [key, field, delta]) => (
+            Method::POST,
+            format!(
+                "hash/{}/{}/incrby/{}",
+                encode(key),
+                encode(field),
+                encode(delta)
+            ),
+        ),
+        ("hexists", [key, field]) => (
+            Method::GET,
+            format!("hash/{}/{}/exists", encode(key), encode(field)),
+        ),
+        ("hlen", [key]) => (Method::GET, format!("hash/{}/len", encode(key))),
+        _ => {
+            println!("Usage: {}", usage(op));
+            return;
+        }
+    };
+    if op == "hgetall" {
+        match cli.request_values(method, &path).await {
+            Ok(values) => {
+                if values.is_empty() {
+                    println!("(empty hash)");
+                }
+                for (i, pair) in values.chunks(2).enumerate() {
+                    let value = pair.get(1).map_or("", String::as_str);
+                    println!("{}) {} = {}", i + 1, pair[0], value);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+        return;
+    }
+    match cli.request_with_body(method, &path, body).await {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}", err),
+    }
+}
@@ -0,0 +1,89 @@
+use reqwest::Method;
+
+use crate::client::{encode, Client};
+
+pub const COMMANDS: [&str; 9] = [
+    "lpush", "rpush", "lpop", "rpop", "lrange", "llen", "lindex", "lset", "ltrim",
+];
+
+fn usage(op: &str) -> &'static str {
+    match op {
+        "lpush" => "lpush <key> <value>",
+        "rpush" => "rpush <key> <value>",
+        "lpop" => "lpop <key>",
+        "rpop" => "rpop <key>",
+        "lrange" => "lrange <key> <start> <stop>",
+        "llen" => "llen <key>",
+        "lindex" => "lindex <key> <index>",
+        "lset" => "lset <key> <index> <value>",
+        _ => "ltrim <key> <start> <stop>",
+    }
+}
+
+pub async fn handle_cmd(cli: &Client, op: &str, args: &[&str]) {
+    // the values go in the body
+    let (method, path, body) = match (op, args) {
+        ("lpush" | "rpush", [key, value]) => (
+            Method::POST,
+            format!("list/{}/{}", encode(key), op),
+            value.to_string(),
+        ),
+        ("lpop" | "rpop", [key]) => (
+            Method::POST,
+            format!("list/{}/{}", encode(key), op),
+            String::new(),
+        ),
+        ("lrange", [key, start, stop]) => (
+            Method::GET,
+            format!("list/{}/{}/{}", encode(key), encode(start), encode(stop)),
+            String::new(),
+        ),
+        ("llen", [key]) => (
+            Method::GET,
+            format!("list/{}/len", encode(key)),
+            String::new(),
+        ),
+        ("lindex", [key, index]) => (
+            Method::GET,
+            format!("list/{}/{}", encode(key), encode(index)),
+            String::new(),
+        ),
+        ("lset", [key, index, value]) => (
+            Method::PUT,
+            format!("list/{}/{}", encode(key), encode(index)),
+            value.to_string(),
+        ),
+        ("ltrim", [key, start, stop]) => (
+            Method::POST,
+            format!(
+                "list/{}/trim/{}/{}",
+                encode(key),
+                encode(start),
+                encode(stop)
+            ),
+            String::new(),
+        ),
+        _ => {
+            println!("Usage: {}", usage(op));
+            return;
+        }
+    };
+    if op == "lrange" {
+        match cli.request_values(method, &path).await {
+            Ok(values) => {
+                if values.is_empty() {
+                    println!("(empty list)");
+                }
+                for (i, value) in values.iter().enumerate() {
+                    println!("{}) {}", i + 1, value);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+        return;
+    }
+    match cli.request_with_body(method, &path, body).await {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}", err),
+    }
+}
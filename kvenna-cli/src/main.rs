@@ -3,6 +3,11 @@ use client::Client;
 use dialoguer::Input;
 
 mod client;
+mod hash;
+mod list;
+mod pubsub;
+mod set;
+mod zset;
 
 struct Options {
     pub host: String,
@@ -67,6 +72,13 @@ async fn main() {
             match op.as_str() {
                 "get" => hanlde_get_cmd(&cli, &args[1..]).await,
                 "put" => hanlde_put_cmd(&cli, &args[1..]).await,
+                op if hash::COMMANDS.contains(&op) => hash::handle_cmd(&cli, op, &args[1..]).await,
+                op if list::COMMANDS.contains(&op) => list::handle_cmd(&cli, op, &args[1..]).await,
+                op if pubsub::COMMANDS.contains(&op) => {
+                    pubsub::handle_cmd(&cli, op, &args[1..]).await
+                }
+                op if set::COMMANDS.contains(&op) => set::handle_cmd(&cli, op, &args[1..]).await,
+                op if zset::COMMANDS.contains(&op) => zset::handle_cmd(&cli, op, &args[1..]).await,
                 _ => println!("Unknown command"),
             }
         }
@@ -0,0 +1,60 @@
+use reqwest::Method;
+
+use crate::client::Client;
+
+pub const COMMANDS: [&str; 4] = ["publish", "subscribe", "psubscribe", "numsub"];
+
+fn usage(op: &str) -> String {
+    match op {
+        "publish" => format!("{} <channel> <message>", op),
+        "subscribe" => format!("{} <channel> [channel ...]", op),
+        "psubscribe" => format!("{} <pattern> [pattern ...]", op),
+        _ => format!("{} <channel>", op),
+    }
+}
+
+// print the messages as `[channel] payload`, prefixed by the pattern for the pattern
+// subscriptions, until the connection is closed or the server drops the subscription
+async fn subscribe(cli: &Client, path: &str) {
+    println!("Reading messages... (press Ctrl-C to quit)");
+    let result = cli
+        .events(path, |event, data| match (event, data) {
+            ("message", [channel, payload @ ..]) => {
+                println!("[{}] {}", channel, payload.join("\n"))
+            }
+            ("pmessage", [pattern, channel, payload @ ..]) => {
+                println!("[{} {}] {}", pattern, channel, payload.join("\n"))
+            }
+            ("error", data) => println!("{}", data.join("\n")),
+            _ => {}
+        })
+        .await;
+    if let Err(err) = result {
+        println!("{}", err);
+    }
+}
+
+pub async fn handle_cmd(cli: &Client, op: &str, args: &[&str]) {
+    match (op, args) {
+        ("publish", [channel, message @ ..]) if !message.is_empty() => {
+            let path = format!("publish/{}", channel);
+            match cli
+                .request_with_body(Method::POST, &path, message.join(" "))
+                .await
+            {
+                Ok(text) => println!("(integer) {}", text),
+                Err(err) => println!("{}", err),
+            }
+        }
+        ("subscribe", [_, ..]) => subscribe(cli, &format!("subscribe/{}", args.join(","))).await,
+        ("psubscribe", [_, ..]) => subscribe(cli, &format!("psubscribe/{}", args.join(","))).await,
+        ("numsub", [channel]) => match cli
+            .request(Method::GET, &format!("channel/{}", channel))
+            .await
+        {
+            Ok(text) => println!("(integer) {}", text),
+            Err(err) => println!("{}", err),
+        },
+        _ => println!("Usage: {}", usage(op)),
+    }
+}
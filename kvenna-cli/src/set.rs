@@ -0,0 +1,92 @@
+use reqwest::Method;
+
+use crate::client::{encode, Client};
+
+pub const COMMANDS: [&str; 13] = [
+    "sadd",
+    "srem",
+    "sismember",
+    "smembers",
+    "scard",
+    "spop",
+    "srandmember",
+    "sunion",
+    "sinter",
+    "sdiff",
+    "sunionstore",
+    "sinterstore",
+    "sdiffstore",
+];
+
+fn usage(op: &str) -> String {
+    match op {
+        "sadd" | "srem" | "sismember" => format!("{} <key> <member>", op),
+        "sunion" | "sinter" | "sdiff" => format!("{} <key> [key ...]", op),
+        "sunionstore" | "sinterstore" | "sdiffstore" => {
+            format!("{} <destination> <key> [key ...]", op)
+        }
+        _ => format!("{} <key>", op),
+    }
+}
+
+// the keys of a set operation, separated by commas
+fn encode_keys(keys: &[&str]) -> String {
+    let keys: Vec<_> = keys.iter().map(|key| encode(key)).collect();
+    keys.join(",")
+}
+
+pub async fn handle_cmd(cli: &Client, op: &str, args: &[&str]) {
+    let mut body = String::new();
+    let (method, path) = match (op, args) {
+        // the member added goes in the body
+        ("sadd", [key, member]) => {
+            body = member.to_string();
+            (Method::PUT, format!("set/{}", encode(key)))
+        }
+        ("srem", [key, member]) => (
+            Method::DELETE,
+            format!("set/{}/{}", encode(key), encode(member)),
+        ),
+        ("sismember", [key, member]) => (
+            Method::GET,
+            format!("set/{}/{}", encode(key), encode(member)),
+        ),
+        ("smembers", [key]) => (Method::GET, format!("set/{}", encode(key))),
+        ("scard", [key]) => (Method::GET, format!("set/{}/card", encode(key))),
+        ("spop", [key]) => (Method::POST, format!("set/{}/pop", encode(key))),
+        ("srandmember", [key]) => (Method::GET, format!("set/{}/random", encode(key))),
+        ("sunion" | "sinter" | "sdiff", [_, ..]) => (
+            Method::GET,
+            format!("sets/{}/{}", &op[1..], encode_keys(args)),
+        ),
+        ("sunionstore" | "sinterstore" | "sdiffstore", [dest, keys @ ..]) if !keys.is_empty() => {
+            let op = op[1..].trim_end_matches("store");
+            (
+                Method::POST,
+                format!("sets/{}/{}/{}", op, encode_keys(keys), encode(dest)),
+            )
+        }
+        _ => {
+            println!("Usage: {}", usage(op));
+            return;
+        }
+    };
+    if ["smembers", "sunion", "sinter", "sdiff"].contains(&op) {
+        match cli.request_values(method, &path).await {
+            Ok(members) => {
+                if members.is_empty() {
+                    println!("(empty set)");
+                }
+                for (i, member) in members.iter().enumerate() {
+                    println!("{}) {}", i + 1, member);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+        return;
+    }
+    match cli.request_with_body(method, &path, body).await {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}", err),
+    }
+}
@@ -0,0 +1,94 @@
+use reqwest::Method;
+
+use crate::client::{encode, Client};
+
+pub const COMMANDS: [&str; 7] = [
+    "zadd",
+    "zrem",
+    "zscore",
+    "zrank",
+    "zrange",
+    "zrangebyscore",
+    "zincrby",
+];
+
+fn usage(op: &str) -> &'static str {
+    match op {
+        "zadd" => "zadd <key> <score> <member>",
+        "zrem" => "zrem <key> <member>",
+        "zscore" => "zscore <key> <member>",
+        "zrank" => "zrank <key> <member>",
+        "zrange" => "zrange <key> <start> <stop>",
+        "zrangebyscore" => "zrangebyscore <key> <min> <max>",
+        _ => "zincrby <key> <delta> <member>",
+    }
+}
+
+pub async fn handle_cmd(cli: &Client, op: &str, args: &[&str]) {
+    let mut body = String::new();
+    let (method, path) = match (op, args) {
+        // the members written go in the body
+        ("zadd", [key, score, member]) => {
+            body = member.to_string();
+            (
+                Method::PUT,
+                format!("zset/{}/{}", encode(key), encode(score)),
+            )
+        }
+        ("zrem", [key, member]) => (
+            Method::DELETE,
+            format!("zset/{}/{}", encode(key), encode(member)),
+        ),
+        ("zscore", [key, member]) => (
+            Method::GET,
+            format!("zset/{}/{}", encode(key), encode(member)),
+        ),
+        ("zrank", [key, member]) => (
+            Method::GET,
+            format!("zset/{}/{}/rank", encode(key), encode(member)),
+        ),
+        ("zrange", [key, start, stop]) => (
+            Method::GET,
+            format!(
+                "zset/{}/range/{}/{}",
+                encode(key),
+                encode(start),
+                encode(stop)
+            ),
+        ),
+        ("zrangebyscore", [key, min, max]) => (
+            Method::GET,
+            format!("zset/{}/score/{}/{}", encode(key), encode(min), encode(max)),
+        ),
+        ("zincrby", [key, delta, member]) => {
+            body = member.to_string();
+            (
+                Method::POST,
+                format!("zset/{}/incrby/{}", encode(key), encode(delta)),
+            )
+        }
+        _ => {
+            println!("Usage: {}", usage(op));
+            return;
+        }
+    };
+    if op == "zrange" || op == "zrangebyscore" {
+        match cli.request_values(method, &path).await {
+            Ok(values) => {
+                if values.is_empty() {
+                    println!("(empty sorted set)");
+                }
+                for (i, pair) in values.chunks(2).enumerate() {
+                    let score = pair.get(1).map_or("", String::as_str);
+                    println!("{}) {} ({})", i + 1, pair[0], score);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+        return;
+    }
+    match cli.request_with_body(method, &path, body).await {
+        Ok(text) => println!("{}", text),
+        Err(err) => println!("{}", err),
+    }
+}
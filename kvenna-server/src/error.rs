@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+use crate::{kvenna::KvennaError, server::errors::ServerError};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Error is any error of the crate, the store and the server have their own error types
+// which convert into it
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Kvenna(#[from] KvennaError),
+    #[error(transparent)]
+    Server(#[from] ServerError),
+}
@@ -0,0 +1,105 @@
+use crate::server::{request::Url, status, Server};
+
+use super::{parse_param, respond, write_values, Store};
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/hash/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).hgetall(&key);
+        respond(c, result, |c, pairs| {
+            // fields and values are written on alternate lines
+            let values: Vec<_> = pairs
+                .into_iter()
+                .flat_map(|(field, value)| [field.into_bytes(), value])
+                .collect();
+            write_values(c, &values)
+        })
+    });
+
+    // the static route shadows a field named `len`, which can still be read through hgetall
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/hash/:key/len"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).hlen(&key);
+        respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/hash/:key/:field"), move |c| {
+        let url = c.req.url.clone();
+        let (key, field) = (
+            url.get_param("key").unwrap(),
+            url.get_param("field").unwrap(),
+        );
+        let result = store.read(key).hget(key, field);
+        respond(c, result, |c, value| {
+            match value {
+                Some(value) => c.write_bytes(&value)?,
+                None => c.status(status::NOT_FOUND),
+            }
+            Ok(())
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/hash/:key/:field/exists"), move |c| {
+        let url = c.req.url.clone();
+        let (key, field) = (
+            url.get_param("key").unwrap(),
+            url.get_param("field").unwrap(),
+        );
+        let result = store.read(key).hexists(key, field);
+        respond(c, result, |c, exists| {
+            Ok(c.write_text(if exists { "1" } else { "0" })?)
+        })
+    });
+
+    // the body is the value
+    let store = kv_store.clone();
+    server.bind_put(&Url::new("/hash/:key/:field"), move |c| {
+        let url = c.req.url.clone();
+        let (key, field) = (
+            url.get_param("key").unwrap(),
+            url.get_param("field").unwrap(),
+        );
+        println!("[HSET] {}.{} -> {} bytes", key, field, c.req.body.len());
+        let result = store.write(key).hset(key, field, &c.req.body);
+        respond(c, result, |c, created| {
+            Ok(c.write_text(if created { "1" } else { "0" })?)
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/hash/:key/:field/incrby/:delta"), move |c| {
+        let url = c.req.url.clone();
+        let (key, field) = (
+            url.get_param("key").unwrap(),
+            url.get_param("field").unwrap(),
+        );
+        let Some(delta) = parse_param(c, "delta") else {
+            return Ok(());
+        };
+        println!("[HINCRBY] {}.{} += {}", key, field, delta);
+        let result = store.write(key).hincrby(key, field, delta);
+        respond(c, result, |c, value| Ok(c.write_text(&value.to_string())?))
+    });
+
+    server.bind_delete(&Url::new("/hash/:key/:field"), move |c| {
+        let url = c.req.url.clone();
+        let (key, field) = (
+            url.get_param("key").unwrap(),
+            url.get_param("field").unwrap(),
+        );
+        println!("[HDEL] {}.{}", key, field);
+        let result = kv_store.write(key).hdel(key, field);
+        respond(c, result, |c, deleted| {
+            if deleted {
+                c.write_text("ok")?;
+            } else {
+                c.status(status::NOT_FOUND);
+            }
+            Ok(())
+        })
+    });
+}
@@ -0,0 +1,99 @@
+use crate::server::{request::Url, status, Context, Server};
+
+use super::{parse_param, respond, write_values, HandleResult, Store};
+
+fn write_popped(c: &mut Context, value: Option<Vec<u8>>) -> HandleResult {
+    match value {
+        Some(value) => c.write_bytes(&value)?,
+        None => c.status(status::NOT_FOUND),
+    }
+    Ok(())
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/list/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).lrange(&key, 0, -1);
+        respond(c, result, |c, values| write_values(c, &values))
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/list/:key/len"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).llen(&key);
+        respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/list/:key/:index"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let Some(index) = parse_param(c, "index") else {
+            return Ok(());
+        };
+        let result = store.read(&key).lindex(&key, index);
+        respond(c, result, write_popped)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/list/:key/:start/:stop"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(start), Some(stop)) = (parse_param(c, "start"), parse_param(c, "stop")) else {
+            return Ok(());
+        };
+        let result = store.read(&key).lrange(&key, start, stop);
+        respond(c, result, |c, values| write_values(c, &values))
+    });
+
+    // the body is the value pushed
+    for (op, front) in [("lpush", true), ("rpush", false)] {
+        let store = kv_store.clone();
+        server.bind_post(&Url::new(&format!("/list/:key/{}", op)), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            let value = &c.req.body;
+            println!("[{}] {} -> {} bytes", op.to_uppercase(), key, value.len());
+            let mut kv_store = store.write(&key);
+            let result = if front {
+                kv_store.lpush(&key, value)
+            } else {
+                kv_store.rpush(&key, value)
+            };
+            respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+        });
+    }
+
+    for (op, front) in [("lpop", true), ("rpop", false)] {
+        let store = kv_store.clone();
+        server.bind_post(&Url::new(&format!("/list/:key/{}", op)), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            println!("[{}] {}", op.to_uppercase(), key);
+            let mut kv_store = store.write(&key);
+            let result = if front {
+                kv_store.lpop(&key)
+            } else {
+                kv_store.rpop(&key)
+            };
+            respond(c, result, write_popped)
+        });
+    }
+
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/list/:key/trim/:start/:stop"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(start), Some(stop)) = (parse_param(c, "start"), parse_param(c, "stop")) else {
+            return Ok(());
+        };
+        let result = store.write(&key).ltrim(&key, start, stop);
+        respond(c, result, |c, _| Ok(c.write_text("ok")?))
+    });
+
+    // the body is the value set
+    server.bind_put(&Url::new("/list/:key/:index"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let Some(index) = parse_param(c, "index") else {
+            return Ok(());
+        };
+        let result = kv_store.write(&key).lset(&key, index, &c.req.body);
+        respond(c, result, |c, _| Ok(c.write_text("ok")?))
+    });
+}
@@ -0,0 +1,191 @@
+pub mod hash;
+pub mod list;
+pub mod namespace;
+pub mod pubsub;
+pub mod set;
+pub mod stream;
+pub mod string;
+pub mod watch;
+pub mod zset;
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use crate::{
+    kvenna::{errors, KvennaError, ShardedKvenna},
+    server::{
+        request::percent_decode_bytes, router::HandleResult, status, status::StatusCode, Context,
+        Server,
+    },
+};
+
+pub type Store = Arc<ShardedKvenna>;
+
+// how often an idle event stream sends a comment, which also detects closed connections
+pub(crate) const KEEP_ALIVE: Duration = Duration::from_secs(15);
+pub(crate) const KEEP_ALIVE_EVENT: &str = ": keep-alive\n\n";
+
+// bind all the kvenna routes to the server. The string values of the default namespace
+// are routed by the whole path, after the routes of the other commands, so a key whose
+// first segment is one of channel, hash, list, ns, psubscribe, publish, scan, set, sets,
+// stream, subscribe, watch or zset may be taken for a command. Such keys can be used in
+// the other namespaces, under /ns/<namespace>/.
+pub fn bind(server: &mut Server, kv_store: Store) {
+    string::bind(server, kv_store.clone());
+    list::bind(server, kv_store.clone());
+    hash::bind(server, kv_store.clone());
+    zset::bind(server, kv_store.clone());
+    set::bind(server, kv_store.clone());
+    stream::bind(server, kv_store.clone());
+    watch::bind(server, kv_store.clone());
+    pubsub::bind(server, kv_store.clone());
+    namespace::bind(server, kv_store);
+}
+
+// the status answering a failed kvenna operation
+fn error_status(err: &KvennaError) -> StatusCode {
+    match err {
+        KvennaError::NamespaceExists(_) | KvennaError::GroupExists(_) => status::CONFLICT,
+        KvennaError::NamespaceNotFound(_) | KvennaError::GroupNotFound(_) => status::NOT_FOUND,
+        KvennaError::NamespaceProtected(_) => status::FORBIDDEN,
+        KvennaError::KeyspaceFull | KvennaError::OutOfMemory => status::INSUFFICIENT_STORAGE,
+        KvennaError::KeyTooLong(_) => status::URI_TOO_LONG,
+        KvennaError::ValueTooLarge(_) => status::PAYLOAD_TOO_LARGE,
+        KvennaError::WrongType => status::CONFLICT,
+        KvennaError::KeyNotFound(_) => status::NOT_FOUND,
+        KvennaError::IndexOutOfRange
+        | KvennaError::NotAnInteger
+        | KvennaError::NotAFloat
+        | KvennaError::InvalidStreamId(_)
+        | KvennaError::InvalidNamespace(_)
+        | KvennaError::InvalidOptions(_) => status::BAD_REQUEST,
+        KvennaError::TtlNotSupported(_) | KvennaError::NotPersisted(..) => status::NOT_IMPLEMENTED,
+        KvennaError::Storage(_) => status::INTERNAL_ERROR,
+    }
+}
+
+// respond with the status matching a failed kvenna operation
+pub(crate) fn write_error(c: &mut Context, err: KvennaError) -> HandleResult {
+    println!("[ERROR] {}", err);
+    c.status(error_status(&err));
+    c.write_text(&err.to_string())?;
+    Ok(())
+}
+
+// respond with the result of a kvenna operation, written by f if it succeeded
+pub(crate) fn respond<T, F>(c: &mut Context, result: errors::Result<T>, f: F) -> HandleResult
+where
+    F: FnOnce(&mut Context, T) -> HandleResult,
+{
+    match result {
+        Ok(value) => f(c, value),
+        Err(err) => write_error(c, err),
+    }
+}
+
+// parse the route param, the status is set to 400 BAD REQUEST if it is malformed
+pub(crate) fn parse_param<T: FromStr>(c: &mut Context, name: &str) -> Option<T> {
+    let param = c.req.url.get_param(name).and_then(|p| p.parse().ok());
+    if param.is_none() {
+        c.status(status::BAD_REQUEST);
+    }
+    param
+}
+
+// the body as text, the status is set to 400 BAD REQUEST if it is not utf-8
+pub(crate) fn body_text(c: &mut Context) -> Option<String> {
+    let text = String::from_utf8(c.req.body.clone()).ok();
+    if text.is_none() {
+        c.status(status::BAD_REQUEST);
+    }
+    text
+}
+
+// parse a body of `name=value` pairs separated by `&` or newlines
+pub(crate) fn parse_pairs(body: &[u8]) -> Option<Vec<(String, String)>> {
+    let body = String::from_utf8_lossy(body);
+    body.split(['&', '\n'])
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+// parse an `application/x-www-form-urlencoded` body, the names must be utf-8 but the
+// values are kept as the bytes they decode to
+pub(crate) fn parse_form(body: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    body.split(|&byte| byte == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let at = pair.iter().position(|&byte| byte == b'=')?;
+            let name = String::from_utf8(percent_decode_bytes(&pair[..at], true)).ok()?;
+            Some((name, percent_decode_bytes(&pair[at + 1..], true)))
+        })
+        .collect()
+}
+
+// append a value as its length in bytes on a line of its own followed by its bytes and
+// a line feed, so that values holding line feeds read back whole
+pub(crate) fn frame_value(body: &mut Vec<u8>, value: &[u8]) {
+    body.extend_from_slice(format!("{}\n", value.len()).as_bytes());
+    body.extend_from_slice(value);
+    body.push(b'\n');
+}
+
+// write a list of values, each one framed by frame_value
+pub(crate) fn write_values(c: &mut Context, values: &[Vec<u8>]) -> HandleResult {
+    let mut body = vec![];
+    for value in values {
+        frame_value(&mut body, value);
+    }
+    c.write_bytes(&body)?;
+    Ok(())
+}
+
+// format a server-sent event, every line of data goes into its own `data` field
+pub(crate) fn format_event(id: Option<u64>, event: &str, data: &str) -> String {
+    let mut text = String::new();
+    if let Some(id) = id {
+        text.push_str(&format!("id: {}\n", id));
+    }
+    text.push_str(&format!("event: {}\n", event));
+    for line in data.split('\n') {
+        text.push_str(&format!("data: {}\n", line));
+    }
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_status() {
+        let cases = [
+            (KvennaError::OutOfMemory, status::INSUFFICIENT_STORAGE),
+            (KvennaError::KeyspaceFull, status::INSUFFICIENT_STORAGE),
+            (KvennaError::WrongType, status::CONFLICT),
+            (KvennaError::GroupExists("g".into()), status::CONFLICT),
+            (KvennaError::KeyNotFound("k".into()), status::NOT_FOUND),
+            (
+                KvennaError::NamespaceProtected("ns".into()),
+                status::FORBIDDEN,
+            ),
+            (KvennaError::KeyTooLong(8), status::URI_TOO_LONG),
+            (KvennaError::ValueTooLarge(8), status::PAYLOAD_TOO_LARGE),
+            (KvennaError::NotAnInteger, status::BAD_REQUEST),
+            (KvennaError::InvalidOptions("o".into()), status::BAD_REQUEST),
+            (
+                KvennaError::TtlNotSupported("disk"),
+                status::NOT_IMPLEMENTED,
+            ),
+            (KvennaError::Storage("io".into()), status::INTERNAL_ERROR),
+        ];
+        for (err, status_code) in cases {
+            assert_eq!(error_status(&err), status_code, "{:?}", err);
+        }
+    }
+}
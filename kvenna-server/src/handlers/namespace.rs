@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use crate::{
+    kvenna::KeyspaceOptions,
+    server::{request::Url, status, Router, Server},
+};
+
+use super::{parse_pairs, string, write_error, Store};
+
+// parse namespace options from a body like `default_ttl=60&max_keys=1000`,
+// where default_ttl is in seconds, max_memory in bytes and eviction names a policy
+// such as `allkeys-lru`. The limits on keys and memory are split between the shards.
+fn parse_options(body: &[u8]) -> Option<KeyspaceOptions> {
+    let mut options = KeyspaceOptions::default();
+    for (name, value) in parse_pairs(body)? {
+        match name.as_str() {
+            "default_ttl" => options.default_ttl = Some(Duration::from_secs(value.parse().ok()?)),
+            "max_keys" => options.max_keys = Some(value.parse().ok()?),
+            "max_memory" => options.max_memory = Some(value.parse().ok()?),
+            "eviction" => options.eviction = value.parse().ok()?,
+            "max_key_len" => options.max_key_len = Some(value.parse().ok()?),
+            "max_value_size" => options.max_value_size = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+// the namespace routes, mounted under /ns. The keys of a namespace come right after
+// its name, so unlike those of the default namespace any key can be used there.
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let (list_store, create_store, drop_store) =
+        (kv_store.clone(), kv_store.clone(), kv_store.clone());
+    let info_store = kv_store.clone();
+    let (get_store, put_store, del_store) = (kv_store.clone(), kv_store.clone(), kv_store);
+    let mut router = Router::new();
+    router
+        .bind_get(&Url::new("/"), move |c| {
+            let names = list_store.namespaces();
+            c.write_text(&names.join("\n"))?;
+            Ok(())
+        })
+        // the number of keys, the memory they take and the engine storing the string
+        // values, summed over the shards, on alternate lines
+        .bind_get(&Url::new("/:namespace"), move |c| {
+            let name = c.req.url.get_param("namespace").unwrap().to_owned();
+            let (mut keys, mut used_memory, mut max_memory, mut engine_bytes) = (0, 0, 0, 0);
+            let mut engine = "";
+            for shard in info_store.shards() {
+                let keyspace = match shard.namespace(&name) {
+                    Ok(keyspace) => keyspace,
+                    Err(err) => return write_error(c, err),
+                };
+                let stats = keyspace.engine_stats();
+                keys += keyspace.len();
+                used_memory += keyspace.used_memory();
+                max_memory += keyspace.options().max_memory.unwrap_or(0);
+                engine_bytes += stats.disk_bytes.unwrap_or(stats.bytes as u64);
+                engine = stats.engine;
+            }
+            let info = format!(
+                "keys\n{}\nused_memory\n{}\nmax_memory\n{}\nengine\n{}\nengine_bytes\n{}",
+                keys, used_memory, max_memory, engine, engine_bytes
+            );
+            c.write_text(&info)?;
+            Ok(())
+        })
+        .bind_put(&Url::new("/:namespace"), move |c| {
+            let name = c.req.url.get_param("namespace").unwrap().to_owned();
+            let Some(options) = parse_options(&c.req.body) else {
+                c.status(status::BAD_REQUEST);
+                return Ok(());
+            };
+            println!("[NS] create {} with {:?}", name, options);
+            match create_store.create_namespace(&name, options) {
+                Ok(()) => {
+                    c.status(status::CREATED);
+                    c.write_text("ok")?;
+                }
+                Err(err) => write_error(c, err)?,
+            }
+            Ok(())
+        })
+        .bind_delete(&Url::new("/:namespace"), move |c| {
+            let name = c.req.url.get_param("namespace").unwrap().to_owned();
+            println!("[NS] drop {}", name);
+            match drop_store.drop_namespace(&name) {
+                Ok(()) => c.write_text("ok")?,
+                Err(err) => write_error(c, err)?,
+            }
+            Ok(())
+        })
+        .bind_get(&Url::new("/:namespace/*key"), move |c| {
+            let url = c.req.url.clone();
+            let (name, key) = (
+                url.get_param("namespace").unwrap(),
+                url.get_param("key").unwrap(),
+            );
+            string::get_value(c, &get_store, name, key)
+        })
+        // the body is the value
+        .bind_put(&Url::new("/:namespace/*key"), move |c| {
+            let url = c.req.url.clone();
+            let (name, key) = (
+                url.get_param("namespace").unwrap(),
+                url.get_param("key").unwrap(),
+            );
+            let val = std::mem::take(&mut c.req.body);
+            match put_store.write(key).namespace_mut(name) {
+                Ok(keyspace) => string::put_value(c, keyspace, key, &val),
+                Err(err) => write_error(c, err),
+            }
+        })
+        .bind_delete(&Url::new("/:namespace/*key"), move |c| {
+            let url = c.req.url.clone();
+            let (name, key) = (
+                url.get_param("namespace").unwrap(),
+                url.get_param("key").unwrap(),
+            );
+            match del_store.write(key).namespace_mut(name) {
+                Ok(keyspace) => string::del_value(c, keyspace, key),
+                Err(err) => write_error(c, err),
+            }
+        });
+    server.mount("/ns", router);
+}
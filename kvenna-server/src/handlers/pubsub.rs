@@ -0,0 +1,93 @@
+use std::sync::mpsc::RecvTimeoutError;
+
+use crate::{
+    kvenna::pubsub::Subscription,
+    server::{headers, request::Url, Context, Server},
+};
+
+use super::{format_event, HandleResult, Store, KEEP_ALIVE, KEEP_ALIVE_EVENT};
+
+// stream the messages of the subscription as server-sent events, a `message` event
+// carries the channel and the payload, a `pmessage` one the pattern first. A client
+// reading too slowly is unsubscribed, it gets an `error` event and the stream ends.
+fn write_messages(c: &mut Context, subscription: Subscription) -> HandleResult {
+    c.res.add_header(headers::CACHE_CONTROL, "no-cache");
+    c.start_stream(headers::CONTENT_EVENT_STREAM)?;
+    loop {
+        let event = match subscription.recv_timeout(KEEP_ALIVE) {
+            Ok(message) => {
+                let payload = String::from_utf8_lossy(&message.payload);
+                match message.pattern {
+                    Some(pattern) => format_event(
+                        None,
+                        "pmessage",
+                        &format!("{}\n{}\n{}", pattern, message.channel, payload),
+                    ),
+                    None => format_event(
+                        None,
+                        "message",
+                        &format!("{}\n{}", message.channel, payload),
+                    ),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => KEEP_ALIVE_EVENT.to_string(),
+            Err(RecvTimeoutError::Disconnected) => {
+                let event = format_event(None, "error", "subscriber fell behind");
+                let _ = c.write_raw(event.as_bytes());
+                return Ok(());
+            }
+        };
+        // the client has gone away, dropping the subscription unsubscribes it
+        if c.write_raw(event.as_bytes()).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    // the body is the message, respond with the number of subscriptions it reached
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/publish/:channel"), move |c| {
+        let channel = c.req.url.get_param("channel").unwrap().to_owned();
+        let pubsub = store.pubsub();
+        let delivered = pubsub.publish(&channel, &c.req.body);
+        println!("[PUBLISH] {} to {}", channel, delivered);
+        c.write_text(&delivered.to_string())?;
+        Ok(())
+    });
+
+    // number of subscriptions to the channel, pattern subscriptions excluded
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/channel/:channel"), move |c| {
+        let channel = c.req.url.get_param("channel").unwrap().to_owned();
+        let subscribers = store.pubsub().subscribers(&channel);
+        c.write_text(&subscribers.to_string())?;
+        Ok(())
+    });
+
+    // channels and patterns are separated by commas
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/subscribe/:channels"), move |c| {
+        // hold the worker before subscribing, so that a refused client is never counted
+        if !c.hold_worker()? {
+            return Ok(());
+        }
+        let channels = c.req.url.get_param("channels").unwrap().to_owned();
+        let channels: Vec<_> = channels.split(',').collect();
+        println!("[SUBSCRIBE] {:?}", channels);
+        let subscription = store.pubsub().subscribe(&channels, &[]);
+        write_messages(c, subscription)
+    });
+
+    let store = kv_store;
+    server.bind_get(&Url::new("/psubscribe/:patterns"), move |c| {
+        if !c.hold_worker()? {
+            return Ok(());
+        }
+        let patterns = c.req.url.get_param("patterns").unwrap().to_owned();
+        let patterns: Vec<_> = patterns.split(',').collect();
+        println!("[PSUBSCRIBE] {:?}", patterns);
+        let subscription = store.pubsub().subscribe(&[], &patterns);
+        write_messages(c, subscription)
+    });
+}
@@ -0,0 +1,121 @@
+use crate::{
+    kvenna::set::SetOp,
+    server::{request::Url, status, Context, Server},
+};
+
+use super::{body_text, parse_param, respond, write_values, HandleResult, Store};
+
+fn write_members(c: &mut Context, members: Vec<String>) -> HandleResult {
+    let values: Vec<_> = members.into_iter().map(String::into_bytes).collect();
+    write_values(c, &values)
+}
+
+fn write_member(c: &mut Context, member: Option<String>) -> HandleResult {
+    match member {
+        Some(member) => c.write_text(&member)?,
+        None => c.status(status::NOT_FOUND),
+    }
+    Ok(())
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/set/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).smembers(&key);
+        respond(c, result, write_members)
+    });
+
+    // the static routes shadow members named `card` and `random`
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/set/:key/card"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).scard(&key);
+        respond(c, result, |c, card| Ok(c.write_text(&card.to_string())?))
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/set/:key/random"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).srandmember(&key);
+        respond(c, result, write_member)
+    });
+
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/set/:key/pop"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        println!("[SPOP] {}", key);
+        let result = store.write(&key).spop(&key);
+        respond(c, result, write_member)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/set/:key/:member"), move |c| {
+        let url = c.req.url.clone();
+        let (key, member) = (
+            url.get_param("key").unwrap(),
+            url.get_param("member").unwrap(),
+        );
+        let result = store.read(key).sismember(key, member);
+        respond(c, result, |c, is_member| {
+            Ok(c.write_text(if is_member { "1" } else { "0" })?)
+        })
+    });
+
+    // the body is the member
+    let store = kv_store.clone();
+    server.bind_put(&Url::new("/set/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let Some(member) = body_text(c) else {
+            return Ok(());
+        };
+        println!("[SADD] {} {}", key, member);
+        let result = store.write(&key).sadd(&key, &member);
+        respond(c, result, |c, added| {
+            Ok(c.write_text(if added { "1" } else { "0" })?)
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_delete(&Url::new("/set/:key/:member"), move |c| {
+        let url = c.req.url.clone();
+        let (key, member) = (
+            url.get_param("key").unwrap(),
+            url.get_param("member").unwrap(),
+        );
+        println!("[SREM] {} {}", key, member);
+        let result = store.write(key).srem(key, member);
+        respond(c, result, |c, removed| {
+            if removed {
+                c.write_text("ok")?;
+            } else {
+                c.status(status::NOT_FOUND);
+            }
+            Ok(())
+        })
+    });
+
+    // set algebra, op is one of `union`, `inter` and `diff`, and keys are separated by commas
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/sets/:op/:keys"), move |c| {
+        let Some(op) = parse_param::<SetOp>(c, "op") else {
+            return Ok(());
+        };
+        let url = c.req.url.clone();
+        let keys: Vec<_> = url.get_param("keys").unwrap().split(',').collect();
+        let result = store.sop(op, &keys);
+        respond(c, result, write_members)
+    });
+
+    server.bind_post(&Url::new("/sets/:op/:keys/:dest"), move |c| {
+        let Some(op) = parse_param::<SetOp>(c, "op") else {
+            return Ok(());
+        };
+        let url = c.req.url.clone();
+        let keys: Vec<_> = url.get_param("keys").unwrap().split(',').collect();
+        let dest = url.get_param("dest").unwrap();
+        println!("[S{:?}STORE] {} <- {:?}", op, dest, keys);
+        let result = kv_store.sopstore(op, dest, &keys);
+        respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+    });
+}
@@ -0,0 +1,213 @@
+use crate::{
+    kvenna::{
+        stream::{StreamEntry, StreamId},
+        KvennaError,
+    },
+    server::{request::Url, status, Context, Server},
+};
+
+use std::time::Duration;
+
+use super::{frame_value, parse_form, respond, write_error, HandleResult, Store};
+
+// each entry is written as a `<id> <number of fields>` line followed by its fields and
+// values, one after the other, framed as in write_values
+fn write_entries(c: &mut Context, entries: Vec<StreamEntry>) -> HandleResult {
+    let mut body = vec![];
+    for entry in entries {
+        body.extend_from_slice(format!("{} {}\n", entry.id, entry.fields.len()).as_bytes());
+        for (field, value) in entry.fields {
+            frame_value(&mut body, field.as_bytes());
+            frame_value(&mut body, &value);
+        }
+    }
+    c.write_bytes(&body)?;
+    Ok(())
+}
+
+// parse a range bound, `-` and `+` are the smallest and the greatest ids, and an end
+// bound without sequence includes all the entries of that millisecond
+fn parse_bound(bound: &str, is_end: bool) -> Result<StreamId, KvennaError> {
+    match bound {
+        "-" => Ok(StreamId::MIN),
+        "+" => Ok(StreamId::MAX),
+        _ if is_end && !bound.contains('-') => {
+            let id: StreamId = bound.parse()?;
+            Ok(StreamId::new(id.ms, u64::MAX))
+        }
+        _ => bound.parse(),
+    }
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    // the fields are sent as an url-encoded form, `field=value&field=value`
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/stream/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let fields = match parse_form(&c.req.body) {
+            Some(fields) if !fields.is_empty() => fields,
+            _ => {
+                c.status(status::BAD_REQUEST);
+                return Ok(());
+            }
+        };
+        let result = store.write(&key).xadd(&key, fields);
+        respond(c, result, |c, id| {
+            println!("[XADD] {} {}", key, id);
+            Ok(c.write_text(&id.to_string())?)
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/stream/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store
+            .read(&key)
+            .xrange(&key, StreamId::MIN, StreamId::MAX, None);
+        respond(c, result, write_entries)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/stream/:key/len"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).xlen(&key);
+        respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/stream/:key/range/:start/:end"), move |c| {
+        let url = c.req.url.clone();
+        let key = url.get_param("key").unwrap();
+        let bounds = parse_bound(url.get_param("start").unwrap(), false)
+            .and_then(|start| Ok((start, parse_bound(url.get_param("end").unwrap(), true)?)));
+        let result = bounds.and_then(|(start, end)| store.read(key).xrange(key, start, end, None));
+        respond(c, result, write_entries)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/stream/:key/after/:id"), move |c| {
+        let url = c.req.url.clone();
+        let key = url.get_param("key").unwrap();
+        let result = url
+            .get_param("id")
+            .unwrap()
+            .parse()
+            .and_then(|id| store.read(key).xread(key, id, None));
+        respond(c, result, write_entries)
+    });
+
+    // start is the id after which the group delivers entries, `$` is the last entry
+    let store = kv_store.clone();
+    server.bind_put(&Url::new("/stream/:key/group/:group/:start"), move |c| {
+        let url = c.req.url.clone();
+        let (key, group) = (
+            url.get_param("key").unwrap(),
+            url.get_param("group").unwrap(),
+        );
+        let start = match url.get_param("start").unwrap() {
+            "$" => None,
+            start => match start.parse() {
+                Ok(start) => Some(start),
+                Err(err) => return write_error(c, err),
+            },
+        };
+        println!("[XGROUP] create {} {}", key, group);
+        let result = store.write(key).xgroup_create(key, group, start);
+        respond(c, result, |c, _| {
+            c.status(status::CREATED);
+            Ok(c.write_text("ok")?)
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_delete(&Url::new("/stream/:key/group/:group"), move |c| {
+        let url = c.req.url.clone();
+        let (key, group) = (
+            url.get_param("key").unwrap(),
+            url.get_param("group").unwrap(),
+        );
+        println!("[XGROUP] destroy {} {}", key, group);
+        let result = store.write(key).xgroup_destroy(key, group);
+        respond(c, result, |c, destroyed| {
+            if destroyed {
+                c.write_text("ok")?;
+            } else {
+                c.status(status::NOT_FOUND);
+            }
+            Ok(())
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/stream/:key/group/:group/:consumer"), move |c| {
+        let url = c.req.url.clone();
+        let (key, group) = (
+            url.get_param("key").unwrap(),
+            url.get_param("group").unwrap(),
+        );
+        let consumer = url.get_param("consumer").unwrap();
+        let result = store.write(key).xreadgroup(key, group, consumer, None);
+        respond(c, result, write_entries)
+    });
+
+    // claim the pending entries of the group delivered at least min_idle milliseconds
+    // ago for the consumer, and deliver them again
+    let store = kv_store.clone();
+    server.bind_post(
+        &Url::new("/stream/:key/group/:group/:consumer/claim/:min_idle"),
+        move |c| {
+            let url = c.req.url.clone();
+            let (key, group) = (
+                url.get_param("key").unwrap(),
+                url.get_param("group").unwrap(),
+            );
+            let consumer = url.get_param("consumer").unwrap();
+            let min_idle = match url.get_param("min_idle").unwrap().parse() {
+                Ok(min_idle) => Duration::from_millis(min_idle),
+                Err(_) => {
+                    c.status(status::BAD_REQUEST);
+                    return Ok(());
+                }
+            };
+            let result = store
+                .write(key)
+                .xclaim(key, group, consumer, min_idle, None);
+            respond(c, result, write_entries)
+        },
+    );
+
+    // ids are separated by commas
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/stream/:key/group/:group/ack/:ids"), move |c| {
+        let url = c.req.url.clone();
+        let (key, group) = (
+            url.get_param("key").unwrap(),
+            url.get_param("group").unwrap(),
+        );
+        let ids: Result<Vec<StreamId>, _> = url
+            .get_param("ids")
+            .unwrap()
+            .split(',')
+            .map(str::parse)
+            .collect();
+        let result = ids.and_then(|ids| store.write(key).xack(key, group, &ids));
+        respond(c, result, |c, acked| Ok(c.write_text(&acked.to_string())?))
+    });
+
+    // pending entries are written one per line as `<id> <consumer> <deliveries>`
+    server.bind_get(&Url::new("/stream/:key/group/:group/pending"), move |c| {
+        let url = c.req.url.clone();
+        let (key, group) = (
+            url.get_param("key").unwrap(),
+            url.get_param("group").unwrap(),
+        );
+        let result = kv_store.read(key).xpending(key, group);
+        respond(c, result, |c, pending| {
+            let lines: Vec<_> = pending
+                .into_iter()
+                .map(|entry| format!("{} {} {}", entry.id, entry.consumer, entry.deliveries))
+                .collect();
+            Ok(c.write_text(&lines.join("\n"))?)
+        })
+    });
+}
@@ -0,0 +1,213 @@
+use std::{io, time::Duration};
+
+use crate::{
+    kvenna::{errors, kvenna::DEFAULT_NAMESPACE, Keyspace},
+    server::{
+        headers, range::ByteRange, request::Url, router::HandleResult, status, Context, Server,
+    },
+};
+
+use super::{respond, write_error, Store};
+
+// larger values are sent in chunks of this size rather than copied into the response
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// read from the keyspace holding key, its shard is locked only meanwhile
+fn read<T>(
+    store: &Store,
+    namespace: &str,
+    key: &str,
+    f: impl FnOnce(&Keyspace) -> errors::Result<T>,
+) -> errors::Result<T> {
+    f(store.read(key).namespace(namespace)?)
+}
+
+// respond with the value of key, or the requested slice of it if there is a range header
+pub(crate) fn get_value(
+    c: &mut Context,
+    store: &Store,
+    namespace: &str,
+    key: &str,
+) -> HandleResult {
+    let range = c
+        .req
+        .headers
+        .get(headers::RANGE)
+        .and_then(|range| ByteRange::parse(&range));
+    let feed = store.feed();
+    // the revision is read under the lock, the later writes to the key come after it
+    let (len, revision) = match read(store, namespace, key, |keyspace| {
+        Ok((keyspace.strlen(key), feed.revision()))
+    }) {
+        Ok(found) => found,
+        Err(err) => return write_error(c, err),
+    };
+    // if val does not exist, we should set the status code as 404 NOT FOUND
+    let Some(len) = len else {
+        c.status(status::NOT_FOUND);
+        return Ok(());
+    };
+    c.res.add_header(headers::ACCEPT_RANGES, "bytes");
+    match range.map(|range| range.resolve(len)) {
+        // no range requested, return the whole value
+        None => {
+            // large values are read from the engine a chunk at a time as they are sent,
+            // the shard being locked only while a chunk is read. A value changed
+            // meanwhile cuts the response short rather than mixing old and new bytes.
+            if len > CHUNK_SIZE {
+                println!("[GET] key = {}, streaming {} bytes", key, len);
+                c.start_chunks(headers::CONTENT_TEXT_HTML)?;
+                for start in (0..len).step_by(CHUNK_SIZE) {
+                    let chunk = read(store, namespace, key, |keyspace| {
+                        if feed.changed_since(revision, namespace, key) {
+                            return Ok(None);
+                        }
+                        keyspace.get_range(key, start, start + CHUNK_SIZE)
+                    })
+                    .map_err(|err| io::Error::other(err.to_string()))?;
+                    let Some(chunk) = chunk else {
+                        return Err(io::Error::other(format!("{} changed while sent", key)).into());
+                    };
+                    c.write_chunk(&chunk)?;
+                }
+                return Ok(());
+            }
+            let val = match read(store, namespace, key, |keyspace| keyspace.get(key)) {
+                Ok(val) => val.unwrap_or_default(),
+                Err(err) => return write_error(c, err),
+            };
+            println!(
+                "[GET] key = {}, got value = {:?}",
+                key,
+                String::from_utf8_lossy(&val)
+            );
+            c.write_bytes(&val)?;
+        }
+        // return only the requested slice as 206 PARTIAL CONTENT
+        Some(Some((start, end))) => {
+            let val = match read(store, namespace, key, |keyspace| {
+                keyspace.get_range(key, start, end + 1)
+            }) {
+                Ok(val) => val.unwrap_or_default(),
+                Err(err) => return write_error(c, err),
+            };
+            println!("[GET] key = {}, got range {}-{}/{}", key, start, end, len);
+            let content_range = format!("bytes {}-{}/{}", start, end, len);
+            c.status(status::PARTIAL_CONTENT);
+            c.res.add_header(headers::CONTENT_RANGE, &content_range);
+            c.write_bytes(&val)?;
+        }
+        Some(None) => {
+            c.status(status::RANGE_NOT_SATISFIABLE);
+            c.res
+                .add_header(headers::CONTENT_RANGE, &format!("bytes */{}", len));
+        }
+    }
+    Ok(())
+}
+
+// put the value of key, expiring after the `ttl` query option in seconds if it is given
+pub(crate) fn put_value(
+    c: &mut Context,
+    keyspace: &mut Keyspace,
+    key: &str,
+    val: &[u8],
+) -> HandleResult {
+    let ttl = match c.req.url.query("ttl").map(str::parse) {
+        None => None,
+        Some(Ok(secs)) => Some(Duration::from_secs(secs)),
+        Some(Err(_)) => {
+            c.status(status::BAD_REQUEST);
+            return Ok(());
+        }
+    };
+    println!("[PUT] {} -> {} bytes", key, val.len());
+    match keyspace.put_with_ttl(key, val, ttl) {
+        Ok(()) => c.write_text("ok")?,
+        Err(err) => write_error(c, err)?,
+    }
+    Ok(())
+}
+
+pub(crate) fn del_value(c: &mut Context, keyspace: &mut Keyspace, key: &str) -> HandleResult {
+    println!("[DELETE] {}", key);
+    match keyspace.remove(key) {
+        Ok(true) => c.write_text("ok")?,
+        Ok(false) => c.status(status::NOT_FOUND),
+        Err(err) => write_error(c, err)?,
+    }
+    Ok(())
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    // the keys from start and the values of the default namespace on alternate lines,
+    // up to end excluded if it is given, sent an entry per chunk
+    for url in ["/scan/:start", "/scan/:start/:end"] {
+        let store = kv_store.clone();
+        server.bind_get(&Url::new(url), move |c| {
+            let url = c.req.url.clone();
+            let start = url.get_param("start").unwrap();
+            c.start_chunks(headers::CONTENT_TEXT_HTML)?;
+            // the entries are read as they are sent, so a failing read can only cut
+            // the response short
+            let (mut first, mut written) = (true, Ok(()));
+            let result = store.scan_each(
+                DEFAULT_NAMESPACE,
+                start,
+                url.get_param("end"),
+                |key, value| {
+                    let mut chunk = if first { vec![] } else { vec![b'\n'] };
+                    first = false;
+                    chunk.extend_from_slice(key.as_bytes());
+                    chunk.push(b'\n');
+                    chunk.extend_from_slice(&value);
+                    written = c.write_chunk(&chunk);
+                    written.is_ok()
+                },
+            );
+            written?;
+            result.map_err(|err| io::Error::other(err.to_string()))?;
+            Ok(())
+        });
+    }
+
+    let (get_store, put_store, patch_store) =
+        (kv_store.clone(), kv_store.clone(), kv_store.clone());
+    server
+        // the keys may hold slashes, like users/42/profile
+        .bind_get(&Url::new("/*key"), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            get_value(c, &get_store, DEFAULT_NAMESPACE, &key)
+        })
+        // the body is the value
+        .bind_put(&Url::new("/*key"), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            let val = std::mem::take(&mut c.req.body);
+            put_value(c, &mut put_store.write(&key), &key, &val)
+        })
+        // overwrite the value from the `offset` query option with the body, or append the
+        // body if there is no offset, and return the new length
+        .bind_patch(&Url::new("/*key"), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            let offset = match c.req.url.query("offset").map(str::parse) {
+                None => None,
+                Some(Ok(offset)) => Some(offset),
+                Some(Err(_)) => {
+                    c.status(status::BAD_REQUEST);
+                    return Ok(());
+                }
+            };
+            let body = std::mem::take(&mut c.req.body);
+            let mut keyspace = patch_store.write(&key);
+            let result = match offset {
+                Some(offset) => keyspace.set_range(&key, offset, &body),
+                None => keyspace.append(&key, &body),
+            };
+            println!("[PATCH] {} <- {} bytes", key, body.len());
+            respond(c, result, |c, len| Ok(c.write_text(&len.to_string())?))
+        })
+        .bind_delete(&Url::new("/*key"), move |c| {
+            let key = c.req.url.get_param("key").unwrap().to_owned();
+            del_value(c, &mut kv_store.write(&key), &key)
+        });
+}
@@ -0,0 +1,134 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    kvenna::{
+        kvenna::DEFAULT_NAMESPACE,
+        watch::{ChangeFeed, Compacted, Event},
+        KvennaError,
+    },
+    server::{headers, request::Url, status, Context, Server},
+};
+
+use super::{format_event, write_error, HandleResult, Store, KEEP_ALIVE, KEEP_ALIVE_EVENT};
+
+// the revision to resume watching from, sent along with the long-poll responses
+const REVISION: &str = "X-Kvenna-Revision";
+// how long a long-poll waits for a change before responding with nothing
+const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn matcher(namespace: String, prefix: String) -> impl Fn(&Event) -> bool {
+    move |event| event.namespace == namespace && event.key.starts_with(&prefix)
+}
+
+// stream the changes of the keys starting with prefix as server-sent events. A client
+// reconnecting with Last-Event-ID receives the changes it missed.
+fn watch_events(
+    c: &mut Context,
+    feed: Arc<ChangeFeed>,
+    filter: impl Fn(&Event) -> bool,
+) -> HandleResult {
+    let mut since = c
+        .req
+        .headers
+        .get(headers::LAST_EVENT_ID)
+        .and_then(|id| id.trim().parse().ok())
+        .unwrap_or_else(|| feed.revision());
+    println!("[WATCH] {} from {}", c.req.url.path(), since);
+    c.res.add_header(headers::CACHE_CONTROL, "no-cache");
+    c.start_stream(headers::CONTENT_EVENT_STREAM)?;
+    loop {
+        let message = match feed.wait(since, &filter, KEEP_ALIVE) {
+            Ok(events) if events.is_empty() => KEEP_ALIVE_EVENT.to_string(),
+            Ok(events) => {
+                since = events.last().unwrap().revision;
+                events
+                    .iter()
+                    .map(|event| {
+                        let kind: &str = event.kind.into();
+                        format_event(Some(event.revision), kind, &event.key)
+                    })
+                    .collect()
+            }
+            // tell the client it missed some changes and go on with the ones still kept
+            Err(Compacted { oldest }) => {
+                since = oldest - 1;
+                format_event(None, "compacted", &oldest.to_string())
+            }
+        };
+        // the client has gone away
+        if c.write_raw(message.as_bytes()).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+// long-poll the changes after revision, the response lists them one per line as
+// `revision kind key` and carries the revision to poll from next
+fn poll_events(
+    c: &mut Context,
+    feed: Arc<ChangeFeed>,
+    filter: impl Fn(&Event) -> bool,
+    revision: u64,
+) -> HandleResult {
+    match feed.wait(revision, filter, POLL_TIMEOUT) {
+        Ok(events) => {
+            let next = events.last().map_or(revision, |event| event.revision);
+            let lines: Vec<_> = events
+                .iter()
+                .map(|event| {
+                    let kind: &str = event.kind.into();
+                    format!("{} {} {}", event.revision, kind, event.key)
+                })
+                .collect();
+            c.res.add_header(REVISION, &next.to_string());
+            c.write_text(&lines.join("\n"))?;
+        }
+        Err(Compacted { oldest }) => {
+            c.status(status::GONE);
+            c.res.add_header(REVISION, &(oldest - 1).to_string());
+            c.write_text(&format!("changes before revision {} are compacted", oldest))?;
+        }
+    }
+    Ok(())
+}
+
+// watch the keys starting with the prefix, which may span several segments, in the
+// namespace given by the `namespace` query or the default one. With a `revision`
+// query the changes after it are long-polled, otherwise they are streamed.
+fn watch(c: &mut Context, store: &Store) -> HandleResult {
+    let prefix = c.req.url.get_param("prefix").unwrap_or_default().to_owned();
+    let namespace = c
+        .req
+        .url
+        .query("namespace")
+        .unwrap_or(DEFAULT_NAMESPACE)
+        .to_owned();
+    if !store.namespaces().contains(&namespace) {
+        return write_error(c, KvennaError::NamespaceNotFound(namespace));
+    }
+    let revision = match c.req.url.query("revision").map(str::parse) {
+        None => None,
+        Some(Ok(revision)) => Some(revision),
+        Some(Err(_)) => {
+            c.status(status::BAD_REQUEST);
+            return Ok(());
+        }
+    };
+    if !c.hold_worker()? {
+        return Ok(());
+    }
+    let feed = store.feed();
+    let filter = matcher(namespace, prefix);
+    match revision {
+        Some(revision) => poll_events(c, feed, filter, revision),
+        None => watch_events(c, feed, filter),
+    }
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/watch"), move |c| watch(c, &store));
+
+    let store = kv_store;
+    server.bind_get(&Url::new("/watch/*prefix"), move |c| watch(c, &store));
+}
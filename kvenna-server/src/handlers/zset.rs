@@ -0,0 +1,120 @@
+use crate::server::{request::Url, status, Context, Server};
+
+use super::{body_text, parse_param, respond, write_values, HandleResult, Store};
+
+// members and scores are written on alternate lines
+fn write_entries(c: &mut Context, entries: Vec<(String, f64)>) -> HandleResult {
+    let values: Vec<_> = entries
+        .into_iter()
+        .flat_map(|(member, score)| [member.into_bytes(), score.to_string().into_bytes()])
+        .collect();
+    write_values(c, &values)
+}
+
+pub fn bind(server: &mut Server, kv_store: Store) {
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/zset/:key"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let result = store.read(&key).zrange(&key, 0, -1);
+        respond(c, result, write_entries)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/zset/:key/range/:start/:stop"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(start), Some(stop)) = (parse_param(c, "start"), parse_param(c, "stop")) else {
+            return Ok(());
+        };
+        let result = store.read(&key).zrange(&key, start, stop);
+        respond(c, result, write_entries)
+    });
+
+    // min and max accept `-inf` and `inf` as well
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/zset/:key/score/:min/:max"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(min), Some(max)) = (parse_param(c, "min"), parse_param(c, "max")) else {
+            return Ok(());
+        };
+        let result = store.read(&key).zrangebyscore(&key, min, max);
+        respond(c, result, write_entries)
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/zset/:key/:member"), move |c| {
+        let url = c.req.url.clone();
+        let (key, member) = (
+            url.get_param("key").unwrap(),
+            url.get_param("member").unwrap(),
+        );
+        let result = store.read(key).zscore(key, member);
+        respond(c, result, |c, score| {
+            match score {
+                Some(score) => c.write_text(&score.to_string())?,
+                None => c.status(status::NOT_FOUND),
+            }
+            Ok(())
+        })
+    });
+
+    let store = kv_store.clone();
+    server.bind_get(&Url::new("/zset/:key/:member/rank"), move |c| {
+        let url = c.req.url.clone();
+        let (key, member) = (
+            url.get_param("key").unwrap(),
+            url.get_param("member").unwrap(),
+        );
+        let result = store.read(key).zrank(key, member);
+        respond(c, result, |c, rank| {
+            match rank {
+                Some(rank) => c.write_text(&rank.to_string())?,
+                None => c.status(status::NOT_FOUND),
+            }
+            Ok(())
+        })
+    });
+
+    // the body is the member
+    let store = kv_store.clone();
+    server.bind_put(&Url::new("/zset/:key/:score"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(score), Some(member)) = (parse_param(c, "score"), body_text(c)) else {
+            return Ok(());
+        };
+        println!("[ZADD] {} {} -> {}", key, member, score);
+        let result = store.write(&key).zadd(&key, score, &member);
+        respond(c, result, |c, created| {
+            Ok(c.write_text(if created { "1" } else { "0" })?)
+        })
+    });
+
+    // the body is the member
+    let store = kv_store.clone();
+    server.bind_post(&Url::new("/zset/:key/incrby/:delta"), move |c| {
+        let key = c.req.url.get_param("key").unwrap().to_owned();
+        let (Some(delta), Some(member)) = (parse_param(c, "delta"), body_text(c)) else {
+            return Ok(());
+        };
+        println!("[ZINCRBY] {} {} += {}", key, member, delta);
+        let result = store.write(&key).zincrby(&key, delta, &member);
+        respond(c, result, |c, score| Ok(c.write_text(&score.to_string())?))
+    });
+
+    server.bind_delete(&Url::new("/zset/:key/:member"), move |c| {
+        let url = c.req.url.clone();
+        let (key, member) = (
+            url.get_param("key").unwrap(),
+            url.get_param("member").unwrap(),
+        );
+        println!("[ZREM] {} {}", key, member);
+        let result = kv_store.write(key).zrem(key, member);
+        respond(c, result, |c, removed| {
+            if removed {
+                c.write_text("ok")?;
+            } else {
+                c.status(status::NOT_FOUND);
+            }
+            Ok(())
+        })
+    });
+}
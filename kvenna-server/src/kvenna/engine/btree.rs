@@ -0,0 +1,95 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound::{Excluded, Included, Unbounded},
+};
+
+use super::{clamp_range, EngineStats, Result, Scan, StorageEngine};
+
+// BTreeEngine is the reference in-memory engine, built over the standard BTreeMap
+#[derive(Default)]
+pub struct BTreeEngine {
+    map: BTreeMap<String, Vec<u8>>,
+    bytes: usize,
+}
+
+impl BTreeEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageEngine for BTreeEngine {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn get_range(&self, key: &str, start: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .map
+            .get(key)
+            .map(|value| value[clamp_range(value.len(), start, end)].to_vec()))
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        match self.map.insert(key.to_string(), value.to_vec()) {
+            Some(old) => self.bytes = self.bytes - old.len() + value.len(),
+            None => self.bytes += key.len() + value.len(),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        let value = self.map.remove(key);
+        if let Some(value) = value.as_ref() {
+            self.bytes -= key.len() + value.len();
+        }
+        Ok(value)
+    }
+
+    fn scan<'a>(&'a self, start: &str, end: Option<&str>) -> Scan<'a> {
+        let end = match end {
+            Some(end) => Excluded(end.to_string()),
+            None => Unbounded,
+        };
+        // an empty range would make BTreeMap::range panic
+        if matches!(&end, Excluded(end) if end.as_str() <= start) {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(
+            self.map
+                .range((Included(start.to_string()), end))
+                .map(|(key, value)| Ok((key.clone(), value.clone()))),
+        )
+    }
+
+    fn stats(&self) -> EngineStats {
+        EngineStats {
+            engine: "btree",
+            keys: self.map.len(),
+            bytes: self.bytes,
+            disk_bytes: None,
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn value_len(&self, key: &str) -> Option<usize> {
+        self.map.get(key).map(|value| value.len())
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn update(&mut self, key: &str, f: &mut dyn FnMut(&mut Vec<u8>)) -> Result<bool> {
+        let Some(value) = self.map.get_mut(key) else {
+            return Ok(false);
+        };
+        let old_len = value.len();
+        f(value);
+        self.bytes = self.bytes - old_len + value.len();
+        Ok(true)
+    }
+}
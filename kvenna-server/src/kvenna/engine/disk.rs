@@ -0,0 +1,458 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Bound::{Excluded, Included, Unbounded},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{clamp_range, EngineStats, KvennaError, Result, Scan, StorageEngine};
+
+const PUT: u8 = 1;
+const DELETE: u8 = 0;
+// checksum, tag, key length and value length
+const HEADER_LEN: usize = 13;
+// the log is compacted once it is twice as large as its live records, and at least this large
+const COMPACTION_THRESHOLD: u64 = 1 << 20;
+
+// SyncPolicy decides when the writes are flushed to the disk. A crash of the process
+// loses nothing, a crash of the machine loses the writes made since the last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    // after every write
+    Always,
+    // after the first write made a second or more after the last sync, and on close
+    #[default]
+    EverySecond,
+    // left to the operating system
+    Never,
+}
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+// parse `always`, `everysec` or `no`
+impl FromStr for SyncPolicy {
+    type Err = KvennaError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(SyncPolicy::Always),
+            "everysec" => Ok(SyncPolicy::EverySecond),
+            "no" => Ok(SyncPolicy::Never),
+            _ => Err(KvennaError::Storage(format!(
+                "unknown fsync policy `{}`",
+                s
+            ))),
+        }
+    }
+}
+
+// crc-32 lookup table, with the reflected ieee polynomial
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    offset: u64,
+    len: usize,
+}
+
+// DiskEngine appends every write to a log file and keeps the position of the latest
+// value of each key in memory, values are read back from the file. The log is replayed
+// when the engine is opened, it is cut at the first record torn by a crash or failing
+// its checksum. Only the string values are persisted, the keyspace refuses the other
+// kinds of values and the ttls.
+pub struct DiskEngine {
+    path: PathBuf,
+    // writes always go to the end of the file, reads seek to the value
+    file: Mutex<File>,
+    index: BTreeMap<String, Location>,
+    // length of the records written whole
+    file_len: u64,
+    // set when a failed write may have left part of a record after file_len, which
+    // has to be cut before anything else is appended
+    torn: bool,
+    sync: SyncPolicy,
+    last_sync: Instant,
+    // bytes taken by the live keys and values
+    bytes: usize,
+}
+
+// a record is the crc-32 of the rest of it, the tag, the lengths of the key and of the
+// value, then the key and the value. The lengths are stored on 4 bytes, longer keys and
+// values are refused.
+fn encode_record(tag: u8, key: &str, value: &[u8]) -> Result<Vec<u8>> {
+    let max = u32::MAX as usize;
+    if key.len() > max {
+        return Err(KvennaError::KeyTooLong(max));
+    }
+    if value.len() > max {
+        return Err(KvennaError::ValueTooLarge(max));
+    }
+    let mut record = Vec::with_capacity(HEADER_LEN + key.len() + value.len());
+    record.extend_from_slice(&[0; 4]);
+    record.push(tag);
+    record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    let checksum = crc32(&record[4..]);
+    record[..4].copy_from_slice(&checksum.to_le_bytes());
+    Ok(record)
+}
+
+impl DiskEngine {
+    pub fn open<P: AsRef<Path>>(path: P, sync: SyncPolicy) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        let mut engine = Self {
+            path,
+            file: Mutex::new(file),
+            index: BTreeMap::new(),
+            file_len: 0,
+            torn: false,
+            sync,
+            last_sync: Instant::now(),
+            bytes: 0,
+        };
+        engine.load()?;
+        Ok(engine)
+    }
+
+    // replay the log to rebuild the index, up to the first record which is not whole
+    // and intact
+    fn load(&mut self) -> Result<()> {
+        let file = self.file.get_mut().unwrap();
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&*file);
+        let mut offset = 0;
+        let mut header = [0; HEADER_LEN];
+        loop {
+            match reader.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            }
+            let checksum = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let tag = header[4];
+            let key_len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+            let value_len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+            let record_len = (HEADER_LEN + key_len + value_len) as u64;
+            // the lengths are checked before anything is allocated for the record
+            if (tag != PUT && tag != DELETE) || offset + record_len > len {
+                break;
+            }
+            let mut record = header[4..].to_vec();
+            record.resize(HEADER_LEN - 4 + key_len + value_len, 0);
+            reader.read_exact(&mut record[HEADER_LEN - 4..])?;
+            if crc32(&record) != checksum {
+                break;
+            }
+            let key_end = HEADER_LEN - 4 + key_len;
+            let Ok(key) = String::from_utf8(record[HEADER_LEN - 4..key_end].to_vec()) else {
+                break;
+            };
+            if let Some(old) = self.index.remove(&key) {
+                self.bytes -= key.len() + old.len;
+            }
+            if tag == PUT {
+                self.bytes += key.len() + value_len;
+                let location = Location {
+                    offset: offset + (HEADER_LEN + key_len) as u64,
+                    len: value_len,
+                };
+                self.index.insert(key, location);
+            }
+            offset += record_len;
+        }
+        drop(reader);
+        // drop the record torn by a crash while writing or damaged, and the ones after it
+        // which would be replayed without the changes it held
+        if offset < len {
+            println!(
+                "[DISK] {} cut at {} bytes out of {}",
+                self.path.display(),
+                offset,
+                len
+            );
+            file.set_len(offset)?;
+        }
+        self.file_len = offset;
+        Ok(())
+    }
+
+    // append a record and return the offset of its value
+    fn append(&mut self, tag: u8, key: &str, value: &[u8]) -> Result<u64> {
+        let record = encode_record(tag, key, value)?;
+        let file = self.file.get_mut().unwrap();
+        if self.torn {
+            file.set_len(self.file_len)?;
+            self.torn = false;
+        }
+        let due = match self.sync {
+            SyncPolicy::Always => true,
+            SyncPolicy::EverySecond => self.last_sync.elapsed() >= SYNC_INTERVAL,
+            SyncPolicy::Never => false,
+        };
+        let written = file
+            .write_all(&record)
+            .and_then(|_| if due { file.sync_data() } else { Ok(()) });
+        if let Err(err) = written {
+            // part of the record may have been written, the next records would follow it
+            // and be dropped with it on reopen
+            self.torn = file.set_len(self.file_len).is_err();
+            return Err(err.into());
+        }
+        if due {
+            self.last_sync = Instant::now();
+        }
+        let offset = self.file_len + (HEADER_LEN + key.len()) as u64;
+        self.file_len += record.len() as u64;
+        Ok(offset)
+    }
+
+    fn read(&self, location: Location) -> Result<Vec<u8>> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut value = vec![0; location.len];
+        file.read_exact(&mut value)?;
+        Ok(value)
+    }
+
+    fn maybe_compact(&mut self) -> Result<()> {
+        let live = (self.bytes + HEADER_LEN * self.index.len()) as u64;
+        if self.file_len < COMPACTION_THRESHOLD || self.file_len < 2 * live {
+            return Ok(());
+        }
+        self.compact()
+    }
+
+    // rewrite the log with the live records only
+    pub fn compact(&mut self) -> Result<()> {
+        let tmp = self.path.with_extension("compact");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        let mut index = BTreeMap::new();
+        let mut offset = 0;
+        for (key, location) in self.index.iter() {
+            let record = encode_record(PUT, key, &self.read(*location)?)?;
+            writer.write_all(&record)?;
+            let value_offset = offset + (HEADER_LEN + key.len()) as u64;
+            index.insert(
+                key.clone(),
+                Location {
+                    offset: value_offset,
+                    ..*location
+                },
+            );
+            offset += record.len() as u64;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.file = Mutex::new(file);
+        self.index = index;
+        self.file_len = offset;
+        self.torn = false;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for DiskEngine {
+    fn drop(&mut self) {
+        if self.sync != SyncPolicy::Never {
+            let _ = self.file.get_mut().unwrap().sync_data();
+        }
+    }
+}
+
+impl StorageEngine for DiskEngine {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.index.get(key) {
+            Some(location) => Ok(Some(self.read(*location)?)),
+            None => Ok(None),
+        }
+    }
+
+    // only the requested bytes are read from the file
+    fn get_range(&self, key: &str, start: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        let Some(location) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let range = clamp_range(location.len, start, end);
+        let location = Location {
+            offset: location.offset + range.start as u64,
+            len: range.len(),
+        };
+        Ok(Some(self.read(location)?))
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let offset = self.append(PUT, key, value)?;
+        let location = Location {
+            offset,
+            len: value.len(),
+        };
+        match self.index.insert(key.to_string(), location) {
+            Some(old) => self.bytes = self.bytes - old.len + value.len(),
+            None => self.bytes += key.len() + value.len(),
+        }
+        self.maybe_compact()
+    }
+
+    fn delete(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(location) = self.index.get(key).copied() else {
+            return Ok(None);
+        };
+        let value = self.read(location)?;
+        self.append(DELETE, key, &[])?;
+        self.index.remove(key);
+        self.bytes -= key.len() + location.len;
+        self.maybe_compact()?;
+        Ok(Some(value))
+    }
+
+    fn scan<'a>(&'a self, start: &str, end: Option<&str>) -> Scan<'a> {
+        let end = match end {
+            Some(end) => Excluded(end.to_string()),
+            None => Unbounded,
+        };
+        // an empty range would make BTreeMap::range panic
+        if matches!(&end, Excluded(end) if end.as_str() <= start) {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(
+            self.index
+                .range((Included(start.to_string()), end))
+                .map(|(key, location)| Ok((key.clone(), self.read(*location)?))),
+        )
+    }
+
+    fn stats(&self) -> EngineStats {
+        EngineStats {
+            engine: "disk",
+            keys: self.index.len(),
+            bytes: self.bytes,
+            disk_bytes: Some(self.file_len),
+        }
+    }
+
+    fn persistent(&self) -> bool {
+        true
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn value_len(&self, key: &str) -> Option<usize> {
+        self.index.get(key).map(|location| location.len)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn destroy(self: Box<Self>) -> Result<()> {
+        let path = self.path.clone();
+        drop(self);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_reopen() {
+        let path = env::temp_dir().join(format!("kvenna-disk-{}.kv", std::process::id()));
+        let mut engine = DiskEngine::open(&path, SyncPolicy::Always).unwrap();
+        engine.put("a", b"1").unwrap();
+        engine.put("b", b"2").unwrap();
+        engine.put("a", b"11").unwrap();
+        engine.delete("b").unwrap();
+        drop(engine);
+
+        // a torn record at the end of the log is dropped
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&encode_record(PUT, "c", b"3").unwrap()[..5])
+            .unwrap();
+        drop(file);
+
+        let mut engine = DiskEngine::open(&path, SyncPolicy::default()).unwrap();
+        assert_eq!(engine.get("a").unwrap(), Some(b"11".to_vec()));
+        assert_eq!(engine.get("b").unwrap(), None);
+        assert_eq!(engine.get("c").unwrap(), None);
+        let len = engine.stats().disk_bytes.unwrap();
+        engine.compact().unwrap();
+        assert!(engine.stats().disk_bytes.unwrap() < len);
+        engine.put("c", b"3").unwrap();
+        assert_eq!(engine.get("a").unwrap(), Some(b"11".to_vec()));
+        assert_eq!(engine.get("c").unwrap(), Some(b"3".to_vec()));
+        Box::new(engine).destroy().unwrap();
+    }
+
+    #[test]
+    fn test_damaged_record() {
+        let path = env::temp_dir().join(format!("kvenna-damaged-{}.kv", std::process::id()));
+        let mut engine = DiskEngine::open(&path, SyncPolicy::Never).unwrap();
+        engine.put("a", b"1").unwrap();
+        engine.put("b", b"2").unwrap();
+        engine.put("c", b"3").unwrap();
+        drop(engine);
+
+        // flip a bit of the value of b, the log is cut before it
+        let mut log = fs::read(&path).unwrap();
+        let b = encode_record(PUT, "a", b"1").unwrap().len();
+        log[b + HEADER_LEN + 1] ^= 1;
+        fs::write(&path, &log).unwrap();
+
+        let engine = DiskEngine::open(&path, SyncPolicy::Never).unwrap();
+        assert_eq!(engine.get("a").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(engine.get("b").unwrap(), None);
+        assert_eq!(engine.get("c").unwrap(), None);
+        assert_eq!(fs::metadata(&path).unwrap().len(), b as u64);
+        Box::new(engine).destroy().unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+#![allow(dead_code)]
+
+pub mod btree;
+pub mod disk;
+pub mod skiplist;
+
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use super::errors::{KvennaError, Result};
+
+pub use btree::BTreeEngine;
+pub use disk::{DiskEngine, SyncPolicy};
+
+pub type Entry = (String, Vec<u8>);
+pub type Scan<'a> = Box<dyn Iterator<Item = Result<Entry>> + 'a>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineStats {
+    pub engine: &'static str,
+    pub keys: usize,
+    // bytes taken by the keys and the values
+    pub bytes: usize,
+    // bytes taken on disk, for the persistent engines
+    pub disk_bytes: Option<u64>,
+}
+
+// the part of [start, end) within a value of len bytes
+pub(super) fn clamp_range(len: usize, start: usize, end: usize) -> Range<usize> {
+    let end = end.min(len);
+    start.min(end)..end
+}
+
+// StorageEngine stores the string values of a keyspace, ordered by key
+pub trait StorageEngine: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    // the bytes of the value in [start, end), None if the key does not exist. Engines
+    // able to read part of a value without the rest should override it.
+    fn get_range(&self, key: &str, start: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .get(key)?
+            .map(|value| value[clamp_range(value.len(), start, end)].to_vec()))
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()>;
+
+    // delete the key and return its value
+    fn delete(&mut self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    // iterate in key order over the entries whose key is in [start, end),
+    // an end of None means up to the last key
+    fn scan<'a>(&'a self, start: &str, end: Option<&str>) -> Scan<'a>;
+
+    fn stats(&self) -> EngineStats;
+
+    // whether the values outlive the process, the keyspace refuses the ttls it could
+    // not persist with them
+    fn persistent(&self) -> bool {
+        false
+    }
+
+    fn contains(&self, key: &str) -> bool;
+
+    // length of the value in bytes, None if the key does not exist
+    fn value_len(&self, key: &str) -> Option<usize>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // mutate the value of an existing key, return false if it does not exist.
+    // Engines able to do it in place should override it.
+    fn update(&mut self, key: &str, f: &mut dyn FnMut(&mut Vec<u8>)) -> Result<bool> {
+        let Some(mut value) = self.get(key)? else {
+            return Ok(false);
+        };
+        f(&mut value);
+        self.put(key, &value)?;
+        Ok(true)
+    }
+
+    // release the engine along with everything it stored, called when its
+    // namespace is dropped
+    fn destroy(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Backend selects the engine every namespace of a Kvenna is built over
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    SkipList,
+    BTreeMap,
+    // one log file per namespace in the directory, holding the string values only
+    Disk(PathBuf, SyncPolicy),
+}
+
+const DISK_EXTENSION: &str = "kv";
+// the file of a disk directory holding the number of shards it is split into
+const SHARDS_FILE: &str = "shards";
+
+impl Backend {
+    // whether the namespaces outlive the process
+    pub fn persistent(&self) -> bool {
+        matches!(self, Backend::Disk(..))
+    }
+
+    // the same backend syncing its writes to the disk with the policy
+    pub fn with_sync(self, sync: SyncPolicy) -> Backend {
+        match self {
+            Backend::Disk(dir, _) => Backend::Disk(dir, sync),
+            backend => backend,
+        }
+    }
+
+    pub fn open(&self, namespace: &str) -> Result<Box<dyn StorageEngine>> {
+        Ok(match self {
+            Backend::SkipList => Box::new(crate::skiplist::SkipList::new()),
+            Backend::BTreeMap => Box::new(BTreeEngine::new()),
+            Backend::Disk(dir, sync) => {
+                fs::create_dir_all(dir)?;
+                let path = dir.join(format!("{}.{}", namespace, DISK_EXTENSION));
+                Box::new(DiskEngine::open(path, *sync)?)
+            }
+        })
+    }
+
+    // the backend of the shard i out of n, the disk shards get their own directory
+    // unless there is a single one. The keys are placed by hash, so a directory has
+    // to be reopened with the same number of shards, see check_shards.
+    pub fn shard(&self, i: usize, n: usize) -> Backend {
+        match self {
+            Backend::Disk(dir, sync) if n > 1 => {
+                Backend::Disk(dir.join(format!("shard-{}", i)), *sync)
+            }
+            backend => backend.clone(),
+        }
+    }
+
+    // record the number of shards in a disk directory the first time it is opened, and
+    // refuse to open it with another number, which would look the keys up in the wrong
+    // shards
+    pub fn check_shards(&self, n: usize) -> Result<()> {
+        let Backend::Disk(dir, _) = self else {
+            return Ok(());
+        };
+        let path = dir.join(SHARDS_FILE);
+        let stored = match fs::read_to_string(&path) {
+            Ok(stored) => Some(stored.trim().parse().map_err(|_| {
+                KvennaError::Storage(format!("invalid shard count in {}", path.display()))
+            })?),
+            // a directory written before the count was recorded
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::stored_shards(dir)?,
+            Err(err) => return Err(err.into()),
+        };
+        match stored {
+            Some(stored) if stored != n => Err(KvennaError::InvalidOptions(format!(
+                "{} holds {} shards, it has to be opened with as many",
+                dir.display(),
+                stored
+            ))),
+            _ => {
+                fs::create_dir_all(dir)?;
+                fs::write(path, n.to_string())?;
+                Ok(())
+            }
+        }
+    }
+
+    // the number of shards found in a directory, None if it holds nothing
+    fn stored_shards(dir: &Path) -> Result<Option<usize>> {
+        if !dir.exists() {
+            return Ok(None);
+        }
+        let mut shards = 0;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir()
+                && entry.file_name().to_string_lossy().starts_with("shard-")
+            {
+                shards += 1;
+            }
+        }
+        if shards > 0 {
+            return Ok(Some(shards));
+        }
+        let namespaces = Backend::Disk(dir.to_owned(), SyncPolicy::Never).namespaces()?;
+        Ok((!namespaces.is_empty()).then_some(1))
+    }
+
+    // the namespaces stored by a persistent backend, in order
+    pub fn namespaces(&self) -> Result<Vec<String>> {
+        let Backend::Disk(dir, _) = self else {
+            return Ok(vec![]);
+        };
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut names = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == DISK_EXTENSION) {
+                if let Some(name) = path.file_stem() {
+                    names.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+// parse `skiplist`, `btree` or `disk:<dir>`, the disk backend syncs every second
+impl FromStr for Backend {
+    type Err = KvennaError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "skiplist" => Ok(Backend::SkipList),
+            None if s == "btree" => Ok(Backend::BTreeMap),
+            Some(("disk", dir)) if !dir.is_empty() => {
+                Ok(Backend::Disk(PathBuf::from(dir), SyncPolicy::default()))
+            }
+            _ => Err(KvennaError::Storage(format!("unknown backend `{}`", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    // every engine has to behave the same
+    fn check_engine(mut engine: Box<dyn StorageEngine>) {
+        assert!(engine.is_empty());
+        engine.put("b", b"2").unwrap();
+        engine.put("a", b"1").unwrap();
+        engine.put("c", b"3").unwrap();
+        engine.put("b", b"22").unwrap();
+        assert_eq!(engine.get("b").unwrap(), Some(b"22".to_vec()));
+        assert_eq!(engine.value_len("b"), Some(2));
+        assert_eq!(engine.get_range("b", 1, 10).unwrap(), Some(b"2".to_vec()));
+        assert_eq!(engine.get_range("b", 5, 10).unwrap(), Some(vec![]));
+        assert_eq!(engine.get_range("z", 0, 1).unwrap(), None);
+        assert!(engine.contains("a"));
+        assert_eq!(engine.len(), 3);
+
+        let scanned: Vec<_> = engine.scan("b", None).map(|e| e.unwrap().0).collect();
+        assert_eq!(scanned, vec!["b", "c"]);
+        let scanned: Vec<_> = engine.scan("", Some("c")).map(|e| e.unwrap().0).collect();
+        assert_eq!(scanned, vec!["a", "b"]);
+
+        assert!(engine.update("a", &mut |v| v.push(b'!')).unwrap());
+        assert!(!engine.update("z", &mut |v| v.push(b'!')).unwrap());
+        assert_eq!(engine.get("a").unwrap(), Some(b"1!".to_vec()));
+
+        assert_eq!(engine.delete("c").unwrap(), Some(b"3".to_vec()));
+        assert_eq!(engine.delete("c").unwrap(), None);
+        let stats = engine.stats();
+        assert_eq!((stats.keys, stats.bytes), (2, 6));
+        engine.destroy().unwrap();
+    }
+
+    #[test]
+    fn test_engines() {
+        let dir = env::temp_dir().join(format!("kvenna-engines-{}", std::process::id()));
+        for backend in [
+            Backend::SkipList,
+            Backend::BTreeMap,
+            Backend::Disk(dir.clone(), SyncPolicy::Always),
+        ] {
+            check_engine(backend.open("test").unwrap());
+        }
+        assert_eq!(
+            Backend::Disk(dir.clone(), SyncPolicy::Never)
+                .namespaces()
+                .unwrap(),
+            Vec::<String>::new()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_shards() {
+        let dir = env::temp_dir().join(format!("kvenna-shards-{}", std::process::id()));
+        let backend = Backend::Disk(dir.clone(), SyncPolicy::Never);
+        backend.check_shards(4).unwrap();
+        backend.check_shards(4).unwrap();
+        assert!(backend.check_shards(2).is_err());
+        // a directory written before the count was recorded
+        fs::remove_file(dir.join(SHARDS_FILE)).unwrap();
+        fs::create_dir(dir.join("shard-0")).unwrap();
+        fs::create_dir(dir.join("shard-1")).unwrap();
+        assert!(backend.check_shards(4).is_err());
+        backend.check_shards(2).unwrap();
+        assert_eq!(fs::read_to_string(dir.join(SHARDS_FILE)).unwrap(), "2");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+use crate::skiplist::SkipList;
+
+use super::{clamp_range, EngineStats, Result, Scan, StorageEngine};
+
+impl StorageEngine for SkipList {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(SkipList::get(self, key))
+    }
+
+    fn get_range(&self, key: &str, start: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        Ok(self.with_value(key, |value| {
+            value[clamp_range(value.len(), start, end)].to_vec()
+        }))
+    }
+
+    fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        SkipList::put(self, key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.del(key))
+    }
+
+    fn scan<'a>(&'a self, start: &str, end: Option<&str>) -> Scan<'a> {
+        let end = end.map(str::to_string);
+        Box::new(
+            self.iter_from(start)
+                .take_while(move |(key, _)| end.as_ref().is_none_or(|end| key < end))
+                .map(Ok),
+        )
+    }
+
+    // the skiplist does not keep track of its bytes, they are counted on demand
+    fn stats(&self) -> EngineStats {
+        EngineStats {
+            engine: "skiplist",
+            keys: self.size(),
+            bytes: self
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum(),
+            disk_bytes: None,
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.with_value(key, |_| ()).is_some()
+    }
+
+    fn value_len(&self, key: &str) -> Option<usize> {
+        self.with_value(key, |value| value.len())
+    }
+
+    fn len(&self) -> usize {
+        self.size()
+    }
+
+    fn update(&mut self, key: &str, f: &mut dyn FnMut(&mut Vec<u8>)) -> Result<bool> {
+        Ok(self.update_with(key, f).is_some())
+    }
+}
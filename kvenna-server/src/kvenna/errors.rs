@@ -0,0 +1,55 @@
+use std::io;
+
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, KvennaError>;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KvennaError {
+    #[error("namespace `{0}` already exists")]
+    NamespaceExists(String),
+    #[error("namespace `{0}` does not exist")]
+    NamespaceNotFound(String),
+    #[error("invalid namespace name `{0}`, only letters, digits, `_` and `-` are allowed")]
+    InvalidNamespace(String),
+    #[error("namespace `{0}` cannot be dropped")]
+    NamespaceProtected(String),
+    #[error("keyspace is full")]
+    KeyspaceFull,
+    #[error("OOM command not allowed when used memory > 'maxmemory'")]
+    OutOfMemory,
+    #[error("key is longer than {0} bytes")]
+    KeyTooLong(usize),
+    #[error("value is larger than {0} bytes")]
+    ValueTooLarge(usize),
+    #[error("WRONGTYPE operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("key `{0}` does not exist")]
+    KeyNotFound(String),
+    #[error("index out of range")]
+    IndexOutOfRange,
+    #[error("value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("value is not a valid float")]
+    NotAFloat,
+    #[error("invalid stream id `{0}`")]
+    InvalidStreamId(String),
+    #[error("consumer group `{0}` already exists")]
+    GroupExists(String),
+    #[error("consumer group `{0}` does not exist")]
+    GroupNotFound(String),
+    #[error("ttls are not supported by the {0} engine")]
+    TtlNotSupported(&'static str),
+    #[error("{0} are not persisted by the {1} engine")]
+    NotPersisted(&'static str, &'static str),
+    #[error("invalid options: {0}")]
+    InvalidOptions(String),
+    #[error("storage error: {0}")]
+    Storage(String),
+}
+
+impl From<io::Error> for KvennaError {
+    fn from(err: io::Error) -> Self {
+        KvennaError::Storage(err.to_string())
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+use super::{
+    errors::{KvennaError, Result},
+    keyspace::Keyspace,
+    memory::element_size,
+    value::ValueKind,
+};
+
+type Hash = HashMap<String, Vec<u8>>;
+
+impl Keyspace {
+    fn hash(&self, key: &str) -> Result<Option<&Hash>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.check_kind(key, ValueKind::Hash)?;
+        self.touch(key);
+        Ok(self.hashes.get(key))
+    }
+
+    fn hash_mut(&mut self, key: &str) -> Result<Option<&mut Hash>> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Hash)?;
+        self.touch(key);
+        Ok(self.hashes.get_mut(key))
+    }
+
+    fn hash_or_insert(&mut self, key: &str, size: isize) -> Result<&mut Hash> {
+        self.check_persisted(ValueKind::Hash)?;
+        self.make_room(key, size)?;
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Hash)?;
+        if !self.hashes.contains_key(key) {
+            self.reserve(key, None)?;
+        }
+        Ok(self.hashes.entry(key.to_string()).or_default())
+    }
+
+    // set the field of the hash, creating it if it does not exist,
+    // return true if the field is new
+    pub fn hset(&mut self, key: &str, field: &str, value: &[u8]) -> Result<bool> {
+        self.check_size(key, field.len() + value.len())?;
+        let hash = self.hash_or_insert(key, element_size(field.len() + value.len()))?;
+        let old = hash.insert(field.to_string(), value.to_vec());
+        let created = old.is_none();
+        let delta = match old {
+            Some(old) => value.len() as isize - old.len() as isize,
+            None => element_size(field.len() + value.len()),
+        };
+        self.grow(key, delta);
+        self.notify_write(key);
+        Ok(created)
+    }
+
+    pub fn hget(&self, key: &str, field: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.hash(key)?.and_then(|hash| hash.get(field).cloned()))
+    }
+
+    // delete the field of the hash, return false if it does not exist
+    pub fn hdel(&mut self, key: &str, field: &str) -> Result<bool> {
+        let Some(hash) = self.hash_mut(key)? else {
+            return Ok(false);
+        };
+        let deleted = hash.remove(field);
+        let emptied = hash.is_empty();
+        if let Some(value) = deleted.as_ref() {
+            self.grow(key, -element_size(field.len() + value.len()));
+        }
+        let deleted = deleted.is_some();
+        // empty hashes are removed, just like in redis
+        if emptied {
+            self.remove_key(key)?;
+        }
+        if deleted {
+            self.notify_write(key);
+        }
+        Ok(deleted)
+    }
+
+    // return all the fields and values of the hash, ordered by field
+    pub fn hgetall(&self, key: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let Some(hash) = self.hash(key)? else {
+            return Ok(vec![]);
+        };
+        let mut pairs: Vec<_> = hash
+            .iter()
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        pairs.sort();
+        Ok(pairs)
+    }
+
+    // increment the integer stored in the field by delta, a missing field counts as 0,
+    // and return the new value
+    pub fn hincrby(&mut self, key: &str, field: &str, delta: i64) -> Result<i64> {
+        let current = match self.hget(key, field)? {
+            Some(value) => String::from_utf8_lossy(&value)
+                .parse::<i64>()
+                .map_err(|_| KvennaError::NotAnInteger)?,
+            None => 0,
+        };
+        let value = current
+            .checked_add(delta)
+            .ok_or(KvennaError::NotAnInteger)?;
+        self.hset(key, field, value.to_string().as_bytes())?;
+        Ok(value)
+    }
+
+    pub fn hexists(&self, key: &str, field: &str) -> Result<bool> {
+        Ok(self.hash(key)?.is_some_and(|hash| hash.contains_key(field)))
+    }
+
+    pub fn hlen(&self, key: &str) -> Result<usize> {
+        Ok(self.hash(key)?.map_or(0, |hash| hash.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        let mut ks = Keyspace::default();
+        assert_eq!(ks.hset("user:1", "name", b"alice"), Ok(true));
+        assert_eq!(ks.hset("user:1", "name", b"bob"), Ok(false));
+        assert_eq!(ks.hincrby("user:1", "visits", 2), Ok(2));
+        assert_eq!(ks.hincrby("user:1", "visits", -5), Ok(-3));
+        assert_eq!(
+            ks.hincrby("user:1", "name", 1),
+            Err(KvennaError::NotAnInteger)
+        );
+
+        assert_eq!(ks.hget("user:1", "name"), Ok(Some(b"bob".to_vec())));
+        assert_eq!(ks.hexists("user:1", "age"), Ok(false));
+        assert_eq!(ks.hlen("user:1"), Ok(2));
+        assert_eq!(
+            ks.hgetall("user:1"),
+            Ok(vec![
+                ("name".to_string(), b"bob".to_vec()),
+                ("visits".to_string(), b"-3".to_vec())
+            ])
+        );
+
+        assert_eq!(ks.hdel("user:1", "name"), Ok(true));
+        assert_eq!(ks.hdel("user:1", "visits"), Ok(true));
+        assert!(!ks.contains("user:1"));
+        ks.rpush("list", b"a").unwrap();
+        assert_eq!(ks.hget("list", "a"), Err(KvennaError::WrongType));
+    }
+}
@@ -0,0 +1,598 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+
+use super::{
+    engine::{EngineStats, Scan, StorageEngine},
+    errors::{KvennaError, Result},
+    memory::{EvictionPolicy, Usage},
+    stream::Stream,
+    value::ValueKind,
+    watch::{ChangeFeed, EventKind},
+    zset::SortedSet,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyspaceOptions {
+    // ttl applied to keys written without an explicit one
+    pub default_ttl: Option<Duration>,
+    // maximum number of keys, writes creating new keys beyond it are rejected
+    pub max_keys: Option<usize>,
+    // approximate memory limit in bytes, keys are evicted beyond it
+    pub max_memory: Option<usize>,
+    pub eviction: EvictionPolicy,
+    // maximum length of a key in bytes
+    pub max_key_len: Option<usize>,
+    // maximum size in bytes of a string value, or of an element of a collection
+    pub max_value_size: Option<usize>,
+}
+
+// hard limit on the size of a value, applied on top of max_value_size so that an offset
+// far past the end of a string can't grow it without bound
+pub const MAX_VALUE_SIZE: usize = 512 * 1024 * 1024;
+
+// Keyspace is a flat keyspace backed by its own storage engine, Kvenna hosts one per
+// namespace. String values live in the engine, the other kinds of values in their own
+// maps, and a key is held by at most one of them.
+pub struct Keyspace {
+    engine: Box<dyn StorageEngine>,
+    pub(super) lists: HashMap<String, VecDeque<Vec<u8>>>,
+    pub(super) hashes: HashMap<String, HashMap<String, Vec<u8>>>,
+    pub(super) zsets: HashMap<String, SortedSet>,
+    pub(super) sets: HashMap<String, HashSet<String>>,
+    pub(super) streams: HashMap<String, Stream>,
+    pub(super) expires: HashMap<String, Instant>,
+    // the keys with a ttl ordered by expiry, for purging and the volatile-ttl eviction
+    pub(super) expiry_order: BTreeSet<(Instant, String)>,
+    pub(super) options: KeyspaceOptions,
+    pub(super) usage: HashMap<String, Usage>,
+    // the tracked keys, indexed by their usage slot so that eviction can sample them
+    pub(super) tracked: Vec<String>,
+    pub(super) used_memory: usize,
+    // logical clock of the accesses, for the lru eviction
+    pub(super) clock: AtomicU64,
+    // namespace name, and the feed and stripe the changes are published to
+    feed: Option<(String, Arc<ChangeFeed>, usize)>,
+}
+
+impl Keyspace {
+    pub fn new(options: KeyspaceOptions) -> Self {
+        // the skiplist is empty, nothing can fail
+        Self::with_engine(options, Box::new(crate::skiplist::SkipList::new())).unwrap()
+    }
+
+    // build the keyspace over the engine, the string values it already holds are
+    // accounted for
+    pub fn with_engine(options: KeyspaceOptions, engine: Box<dyn StorageEngine>) -> Result<Self> {
+        if options.default_ttl.is_some() && engine.persistent() {
+            let err = KvennaError::TtlNotSupported(engine.stats().engine);
+            // don't leave behind the file of a namespace which is never created
+            if engine.is_empty() {
+                engine.destroy()?;
+            }
+            return Err(err);
+        }
+        let stored = engine
+            .scan("", None)
+            .map(|entry| entry.map(|(key, value)| (key, value.len())))
+            .collect::<Result<Vec<_>>>()?;
+        let mut keyspace = Self {
+            engine,
+            lists: HashMap::new(),
+            hashes: HashMap::new(),
+            zsets: HashMap::new(),
+            sets: HashMap::new(),
+            streams: HashMap::new(),
+            expires: HashMap::new(),
+            expiry_order: BTreeSet::new(),
+            options,
+            usage: HashMap::new(),
+            tracked: Vec::new(),
+            used_memory: 0,
+            clock: AtomicU64::new(0),
+            feed: None,
+        };
+        for (key, len) in stored {
+            keyspace.grow(&key, len as isize);
+        }
+        Ok(keyspace)
+    }
+
+    pub(super) fn attach_feed(&mut self, namespace: &str, feed: Arc<ChangeFeed>, stripe: usize) {
+        self.feed = Some((namespace.to_string(), feed, stripe));
+    }
+
+    pub(super) fn notify(&self, key: &str, kind: EventKind) {
+        if let Some((namespace, feed, stripe)) = self.feed.as_ref() {
+            feed.publish(*stripe, namespace, key, kind);
+        }
+    }
+
+    // publish a put if the key still exists after a write, a delete otherwise
+    pub(super) fn notify_write(&self, key: &str) {
+        let kind = if self.contains(key) {
+            EventKind::Put
+        } else {
+            EventKind::Delete
+        };
+        self.notify(key, kind);
+    }
+
+    pub fn options(&self) -> &KeyspaceOptions {
+        &self.options
+    }
+
+    // number of keys, including the expired ones which have not been purged yet
+    pub fn len(&self) -> usize {
+        self.engine.len()
+            + self.lists.len()
+            + self.hashes.len()
+            + self.zsets.len()
+            + self.sets.len()
+            + self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(super) fn is_expired(&self, key: &str) -> bool {
+        match self.expires.get(key) {
+            Some(at) => *at <= Instant::now(),
+            None => false,
+        }
+    }
+
+    pub(super) fn purge_if_expired(&mut self, key: &str) -> Result<()> {
+        if self.is_expired(key) {
+            self.remove_key(key)?;
+            self.notify(key, EventKind::Expire);
+        }
+        Ok(())
+    }
+
+    // remove the key whatever kind of value it holds
+    pub(super) fn remove_key(&mut self, key: &str) -> Result<()> {
+        self.engine.delete(key)?;
+        self.lists.remove(key);
+        self.hashes.remove(key);
+        self.zsets.remove(key);
+        self.sets.remove(key);
+        self.streams.remove(key);
+        self.clear_expiry(key);
+        self.untrack(key);
+        Ok(())
+    }
+
+    // remove all expired keys and return them
+    pub fn purge_expired(&mut self) -> Result<Vec<String>> {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .expiry_order
+            .iter()
+            .take_while(|(at, _)| *at <= now)
+            .map(|(_, key)| key.clone())
+            .collect();
+        for key in expired.iter() {
+            self.remove_key(key)?;
+            self.notify(key, EventKind::Expire);
+        }
+        Ok(expired)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.kind(key).is_some()
+    }
+
+    // return the kind of value held by the key, or None if the key does not exist
+    pub fn kind(&self, key: &str) -> Option<ValueKind> {
+        if self.is_expired(key) {
+            None
+        } else if self.engine.contains(key) {
+            Some(ValueKind::String)
+        } else if self.lists.contains_key(key) {
+            Some(ValueKind::List)
+        } else if self.hashes.contains_key(key) {
+            Some(ValueKind::Hash)
+        } else if self.zsets.contains_key(key) {
+            Some(ValueKind::SortedSet)
+        } else if self.sets.contains_key(key) {
+            Some(ValueKind::Set)
+        } else if self.streams.contains_key(key) {
+            Some(ValueKind::Stream)
+        } else {
+            None
+        }
+    }
+
+    // fail with WrongType if the key exists and holds another kind of value
+    pub(super) fn check_kind(&self, key: &str, kind: ValueKind) -> Result<()> {
+        match self.kind(key) {
+            Some(actual) if actual != kind => Err(KvennaError::WrongType),
+            _ => Ok(()),
+        }
+    }
+
+    // fail if the key or a value of len bytes is beyond the limits of the keyspace,
+    // checked before anything is written
+    pub(super) fn check_size(&self, key: &str, len: usize) -> Result<()> {
+        if let Some(max_key_len) = self.options.max_key_len {
+            if key.len() > max_key_len {
+                return Err(KvennaError::KeyTooLong(max_key_len));
+            }
+        }
+        let max_value_size = self
+            .options
+            .max_value_size
+            .map_or(MAX_VALUE_SIZE, |max| max.min(MAX_VALUE_SIZE));
+        if len > max_value_size {
+            return Err(KvennaError::ValueTooLarge(max_value_size));
+        }
+        Ok(())
+    }
+
+    // make sure a new key can be created, the key must not exist yet
+    pub(super) fn reserve(&mut self, key: &str, ttl: Option<Duration>) -> Result<()> {
+        if let Some(max_keys) = self.options.max_keys {
+            if self.len() >= max_keys {
+                self.purge_expired()?;
+                if self.len() >= max_keys {
+                    return Err(KvennaError::KeyspaceFull);
+                }
+            }
+        }
+        self.set_expiry(key, ttl.or(self.options.default_ttl));
+        Ok(())
+    }
+
+    // the persistent engines keep the values across restarts but not their ttls, which
+    // would make them outlive their expiry
+    fn check_ttl(&self, ttl: Option<Duration>) -> Result<()> {
+        if ttl.is_some() && self.engine.persistent() {
+            return Err(KvennaError::TtlNotSupported(self.engine.stats().engine));
+        }
+        Ok(())
+    }
+
+    // the persistent engines only store the string values, the other kinds would be
+    // lost on restart
+    pub(super) fn check_persisted(&self, kind: ValueKind) -> Result<()> {
+        if !self.engine.persistent() {
+            return Ok(());
+        }
+        let kinds = match kind {
+            ValueKind::String => return Ok(()),
+            ValueKind::List => "lists",
+            ValueKind::Hash => "hashes",
+            ValueKind::SortedSet => "sorted sets",
+            ValueKind::Set => "sets",
+            ValueKind::Stream => "streams",
+        };
+        Err(KvennaError::NotPersisted(kinds, self.engine.stats().engine))
+    }
+
+    fn set_expiry(&mut self, key: &str, ttl: Option<Duration>) {
+        self.clear_expiry(key);
+        if let Some(ttl) = ttl {
+            let at = Instant::now() + ttl;
+            self.expires.insert(key.to_string(), at);
+            self.expiry_order.insert((at, key.to_string()));
+        }
+    }
+
+    fn clear_expiry(&mut self, key: &str) {
+        if let Some(at) = self.expires.remove(key) {
+            self.expiry_order.remove(&(at, key.to_string()));
+        }
+    }
+
+    pub fn put(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        self.put_with_ttl(key, value, None)
+    }
+
+    pub fn put_string(&mut self, key: &str, value: &str) -> Result<()> {
+        self.put(key, value.as_bytes())
+    }
+
+    // put the value, expiring it after ttl, or after the default ttl of the keyspace if
+    // ttl is None. Overwriting a value of any kind resets its expiry.
+    pub fn put_with_ttl(&mut self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<()> {
+        self.check_size(key, value.len())?;
+        self.check_ttl(ttl)?;
+        self.make_room(
+            key,
+            value.len() as isize - self.strlen(key).unwrap_or(0) as isize,
+        )?;
+        self.purge_if_expired(key)?;
+        let old_len = self.strlen(key).unwrap_or(0);
+        match self.kind(key) {
+            None => self.reserve(key, ttl)?,
+            Some(kind) => {
+                if kind != ValueKind::String {
+                    self.remove_key(key)?;
+                }
+                self.set_expiry(key, ttl.or(self.options.default_ttl));
+            }
+        }
+        self.engine.put(key, value)?;
+        self.grow(key, value.len() as isize - old_len as isize);
+        self.notify(key, EventKind::Put);
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.touch(key);
+        self.engine.get(key)
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .get(key)?
+            .map(|val| String::from_utf8_lossy(&val).to_string()))
+    }
+
+    pub fn del(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.purge_if_expired(key)?;
+        let exists = self.contains(key);
+        let value = self.engine.get(key)?;
+        self.remove_key(key)?;
+        if exists {
+            self.notify(key, EventKind::Delete);
+        }
+        Ok(value)
+    }
+
+    // remove the key whatever kind of value it holds, return false if it does not exist
+    pub fn remove(&mut self, key: &str) -> Result<bool> {
+        let exists = self.contains(key);
+        self.remove_key(key)?;
+        if exists {
+            self.notify(key, EventKind::Delete);
+        }
+        Ok(exists)
+    }
+
+    // set the ttl of an existing key, return false if the key does not exist
+    pub fn expire(&mut self, key: &str, ttl: Duration) -> Result<bool> {
+        self.check_ttl(Some(ttl))?;
+        if !self.contains(key) {
+            return Ok(false);
+        }
+        self.set_expiry(key, Some(ttl));
+        Ok(true)
+    }
+
+    // return the remaining ttl of the key, None if the key does not exist,
+    // Some(None) if it never expires
+    pub fn ttl(&self, key: &str) -> Option<Option<Duration>> {
+        if !self.contains(key) {
+            return None;
+        }
+        let now = Instant::now();
+        Some(self.expires.get(key).map(|at| at.duration_since(now)))
+    }
+
+    // return the length of the value in bytes, or None if the key does not exist
+    pub fn strlen(&self, key: &str) -> Option<usize> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.touch(key);
+        self.engine.value_len(key)
+    }
+
+    // append bytes to the end of the value, creating it if it does not exist,
+    // and return the new length
+    pub fn append(&mut self, key: &str, bytes: &[u8]) -> Result<usize> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::String)?;
+        self.check_size(key, self.strlen(key).unwrap_or(0) + bytes.len())?;
+        self.make_room(key, bytes.len() as isize)?;
+        let mut len = 0;
+        let appended = self.engine.update(key, &mut |value| {
+            value.extend_from_slice(bytes);
+            len = value.len();
+        })?;
+        if !appended {
+            self.reserve(key, None)?;
+            self.engine.put(key, bytes)?;
+            len = bytes.len();
+        }
+        self.grow(key, bytes.len() as isize);
+        self.notify(key, EventKind::Put);
+        Ok(len)
+    }
+
+    // return the bytes in [start, end) of the value, both ends are clamped to its length
+    pub fn get_range(&self, key: &str, start: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.touch(key);
+        self.engine.get_range(key, start, end)
+    }
+
+    // overwrite the value starting at offset, padding with zero bytes if the value is
+    // shorter than offset, and return the new length
+    pub fn set_range(&mut self, key: &str, offset: usize, bytes: &[u8]) -> Result<usize> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::String)?;
+        let old_len = self.strlen(key).unwrap_or(0);
+        // an overflowing end saturates and then fails the size check
+        let end = offset.saturating_add(bytes.len());
+        self.check_size(key, old_len.max(end))?;
+        self.make_room(key, end as isize - old_len as isize)?;
+        let overwrite = |value: &mut Vec<u8>| {
+            if value.len() < end {
+                value.resize(end, 0);
+            }
+            value[offset..end].copy_from_slice(bytes);
+        };
+        let mut len = 0;
+        let updated = self.engine.update(key, &mut |value| {
+            overwrite(value);
+            len = value.len();
+        })?;
+        if !updated {
+            self.reserve(key, None)?;
+            let mut value = vec![];
+            overwrite(&mut value);
+            self.engine.put(key, &value)?;
+            len = value.len();
+        }
+        self.grow(key, len as isize - old_len as isize);
+        self.notify(key, EventKind::Put);
+        Ok(len)
+    }
+}
+
+impl Keyspace {
+    // return in key order the string values whose key is in [start, end),
+    // an end of None means up to the last key
+    pub fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<(String, Vec<u8>)>> {
+        self.scan_iter(start, end).collect()
+    }
+
+    // like scan, but the values are read from the engine as the iterator goes
+    pub fn scan_iter<'a>(&'a self, start: &str, end: Option<&str>) -> Scan<'a> {
+        Box::new(
+            self.engine
+                .scan(start, end)
+                .filter(|entry| !matches!(entry, Ok((key, _)) if self.is_expired(key))),
+        )
+    }
+
+    pub fn engine_stats(&self) -> EngineStats {
+        self.engine.stats()
+    }
+
+    // drop the keyspace along with what its engine stored
+    pub(super) fn destroy(self) -> Result<()> {
+        self.engine.destroy()
+    }
+}
+
+impl Default for Keyspace {
+    fn default() -> Self {
+        Self::new(KeyspaceOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, thread};
+
+    use super::{
+        super::engine::{DiskEngine, SyncPolicy},
+        *,
+    };
+
+    #[test]
+    fn test_append_and_ranges() {
+        let mut ks = Keyspace::default();
+        assert_eq!(ks.append("log", b"hello"), Ok(5));
+        assert_eq!(ks.append("log", b" world"), Ok(11));
+        assert_eq!(ks.get_range("log", 6, 11), Ok(Some(b"world".to_vec())));
+        assert_eq!(ks.get_range("log", 8, 100), Ok(Some(b"rld".to_vec())));
+        assert_eq!(ks.get_range("missing", 0, 1), Ok(None));
+
+        assert_eq!(ks.set_range("log", 0, b"HELLO"), Ok(11));
+        assert_eq!(ks.get_string("log"), Ok(Some("HELLO world".to_string())));
+        assert_eq!(ks.set_range("blob", 2, b"ab"), Ok(4));
+        assert_eq!(ks.get("blob"), Ok(Some(vec![0, 0, b'a', b'b'])));
+    }
+
+    #[test]
+    fn test_put_after_del() {
+        let mut ks = Keyspace::default();
+        ks.put_string("key", "a").unwrap();
+        assert_eq!(ks.del("key"), Ok(Some(b"a".to_vec())));
+        assert_eq!(ks.get("key"), Ok(None));
+        ks.put_string("key", "b").unwrap();
+        assert_eq!(ks.get_string("key"), Ok(Some("b".to_string())));
+    }
+
+    #[test]
+    fn test_options() {
+        let mut ks = Keyspace::new(KeyspaceOptions {
+            default_ttl: Some(Duration::from_millis(20)),
+            max_keys: Some(1),
+            ..Default::default()
+        });
+        ks.put_string("a", "1").unwrap();
+        assert_eq!(ks.put_string("b", "2"), Err(KvennaError::KeyspaceFull));
+        // overwriting an existing key is always allowed
+        ks.put_string("a", "3").unwrap();
+        assert!(ks.ttl("a").unwrap().is_some());
+
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(ks.get("a"), Ok(None));
+        // the expired key no longer counts against the limit
+        ks.put_string("b", "2").unwrap();
+        assert_eq!(ks.get_string("b"), Ok(Some("2".to_string())));
+    }
+
+    #[test]
+    fn test_persistent_ttl() {
+        let path = env::temp_dir().join(format!("kvenna-ttl-{}.kv", std::process::id()));
+        let engine = || Box::new(DiskEngine::open(&path, SyncPolicy::Never).unwrap());
+        let options = KeyspaceOptions {
+            default_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert!(Keyspace::with_engine(options, engine()).is_err());
+        assert!(!path.exists());
+
+        let mut ks = Keyspace::with_engine(KeyspaceOptions::default(), engine()).unwrap();
+        let ttl = Duration::from_secs(60);
+        let refused = Err(KvennaError::TtlNotSupported("disk"));
+        assert_eq!(ks.put_with_ttl("a", b"1", Some(ttl)), refused);
+        ks.put("a", b"1").unwrap();
+        assert_eq!(ks.expire("a", ttl).map(|_| ()), refused);
+        // only the strings are persisted
+        assert_eq!(
+            ks.sadd("s", "m"),
+            Err(KvennaError::NotPersisted("sets", "disk"))
+        );
+        assert!(!ks.contains("s"));
+        drop(ks);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let mut ks = Keyspace::new(KeyspaceOptions {
+            max_key_len: Some(4),
+            max_value_size: Some(8),
+            ..Default::default()
+        });
+        assert_eq!(ks.put("long key", b"v"), Err(KvennaError::KeyTooLong(4)));
+        assert_eq!(ks.put("key", &[0; 9]), Err(KvennaError::ValueTooLarge(8)));
+        ks.put("key", b"12345").unwrap();
+        assert_eq!(
+            ks.append("key", b"6789"),
+            Err(KvennaError::ValueTooLarge(8))
+        );
+        assert_eq!(
+            ks.set_range("key", 8, b"!"),
+            Err(KvennaError::ValueTooLarge(8))
+        );
+        assert_eq!(ks.get("key"), Ok(Some(b"12345".to_vec())));
+        assert_eq!(
+            Keyspace::new(KeyspaceOptions::default()).set_range("key", usize::MAX, b"!"),
+            Err(KvennaError::ValueTooLarge(MAX_VALUE_SIZE))
+        );
+        assert_eq!(
+            ks.rpush("list", &[0; 9]),
+            Err(KvennaError::ValueTooLarge(8))
+        );
+        assert!(!ks.contains("list"));
+    }
+}
@@ -1,36 +1,234 @@
 #![allow(dead_code)]
 
-use crate::skiplist::SkipList;
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
+use super::{
+    engine::Backend,
+    errors::{KvennaError, Result},
+    keyspace::{Keyspace, KeyspaceOptions},
+    pubsub::PubSub,
+    watch::ChangeFeed,
+};
+
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+// Kvenna hosts several named keyspaces, it derefs to the default one so that
+// single-namespace users can ignore namespaces altogether
 pub struct Kvenna {
-    skiplist: SkipList,
+    keyspaces: HashMap<String, Keyspace>,
+    feed: Arc<ChangeFeed>,
+    // the stripe of the feed this Kvenna publishes to
+    stripe: usize,
+    pubsub: Arc<PubSub>,
+    backend: Backend,
 }
 
 impl Kvenna {
     pub fn new() -> Self {
-        Self {
-            skiplist: SkipList::new(),
+        Self::with_options(KeyspaceOptions::default())
+    }
+
+    // create an in-memory Kvenna whose default namespace has the options
+    pub fn with_options(options: KeyspaceOptions) -> Self {
+        // opening the skiplist engine never fails
+        Self::open(Backend::SkipList, options).unwrap()
+    }
+
+    // create a Kvenna whose namespaces are stored by the backend, the default one
+    // with the options. The namespaces the backend already holds are reopened with
+    // the default options.
+    pub fn open(backend: Backend, options: KeyspaceOptions) -> Result<Self> {
+        let (feed, pubsub) = (Arc::new(ChangeFeed::new()), Arc::new(PubSub::new()));
+        Self::open_shared(backend, options, feed, 0, pubsub)
+    }
+
+    // open a Kvenna publishing its changes to a stripe of a feed and hosting channels
+    // shared with others
+    pub(super) fn open_shared(
+        backend: Backend,
+        options: KeyspaceOptions,
+        feed: Arc<ChangeFeed>,
+        stripe: usize,
+        pubsub: Arc<PubSub>,
+    ) -> Result<Self> {
+        let mut kv = Self {
+            keyspaces: HashMap::new(),
+            feed,
+            stripe,
+            pubsub,
+            backend,
+        };
+        kv.insert_keyspace(DEFAULT_NAMESPACE, options)?;
+        for name in kv.backend.namespaces()? {
+            if !kv.keyspaces.contains_key(&name) {
+                kv.insert_keyspace(&name, KeyspaceOptions::default())?;
+            }
+        }
+        Ok(kv)
+    }
+
+    fn insert_keyspace(&mut self, name: &str, options: KeyspaceOptions) -> Result<()> {
+        let mut keyspace = Keyspace::with_engine(options, self.backend.open(name)?)?;
+        keyspace.attach_feed(name, self.feed.clone(), self.stripe);
+        self.keyspaces.insert(name.to_string(), keyspace);
+        Ok(())
+    }
+
+    // the feed of the changes made to all namespaces, watchers wait on it without
+    // holding the store
+    pub fn feed(&self) -> Arc<ChangeFeed> {
+        self.feed.clone()
+    }
+
+    // the channels of the server, they live beside the namespaces and hold no keys
+    pub fn pubsub(&self) -> Arc<PubSub> {
+        self.pubsub.clone()
+    }
+
+    pub fn create_namespace(&mut self, name: &str, options: KeyspaceOptions) -> Result<()> {
+        // the name ends up in file names with the persistent backends
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.is_empty() || !name.chars().all(valid) {
+            return Err(KvennaError::InvalidNamespace(name.to_string()));
+        }
+        if self.keyspaces.contains_key(name) {
+            return Err(KvennaError::NamespaceExists(name.to_string()));
+        }
+        // the namespaces are reopened with the default options
+        if self.backend.persistent() && options != KeyspaceOptions::default() {
+            return Err(KvennaError::NotPersisted("namespace options", "disk"));
+        }
+        self.insert_keyspace(name, options)
+    }
+
+    pub fn drop_namespace(&mut self, name: &str) -> Result<()> {
+        if name == DEFAULT_NAMESPACE {
+            return Err(KvennaError::NamespaceProtected(name.to_string()));
+        }
+        match self.keyspaces.remove(name) {
+            Some(keyspace) => keyspace.destroy(),
+            None => Err(KvennaError::NamespaceNotFound(name.to_string())),
         }
     }
 
-    pub fn put(&mut self, key: &str, value: &[u8]) {
-        self.skiplist.put(key, value);
+    // return the names of all namespaces in order
+    pub fn namespaces(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.keyspaces.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn namespace(&self, name: &str) -> Result<&Keyspace> {
+        self.keyspaces
+            .get(name)
+            .ok_or_else(|| KvennaError::NamespaceNotFound(name.to_string()))
     }
 
-    pub fn put_string(&mut self, key: &str, value: &str) {
-        self.put(key, value.as_bytes());
+    pub fn namespace_mut(&mut self, name: &str) -> Result<&mut Keyspace> {
+        self.keyspaces
+            .get_mut(name)
+            .ok_or_else(|| KvennaError::NamespaceNotFound(name.to_string()))
     }
 
-    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
-        self.skiplist.get(key)
+    // remove expired keys of all namespaces, return the number of removed keys
+    pub fn purge_expired(&mut self) -> Result<usize> {
+        let mut purged = 0;
+        for keyspace in self.keyspaces.values_mut() {
+            purged += keyspace.purge_expired()?.len();
+        }
+        Ok(purged)
     }
+}
 
-    pub fn get_string(&self, key: &str) -> Option<String> {
-        self.get(key)
-            .map(|val| String::from_utf8_lossy(&val).to_string())
+impl Default for Kvenna {
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+impl Deref for Kvenna {
+    type Target = Keyspace;
+    fn deref(&self) -> &Self::Target {
+        &self.keyspaces[DEFAULT_NAMESPACE]
+    }
+}
+
+impl DerefMut for Kvenna {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.keyspaces.get_mut(DEFAULT_NAMESPACE).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{super::watch::EventKind, *};
+
+    #[test]
+    fn test_namespaces() {
+        let mut kv = Kvenna::new();
+        kv.put_string("key", "default").unwrap();
+        kv.create_namespace("users", KeyspaceOptions::default())
+            .unwrap();
+        assert_eq!(
+            kv.create_namespace("users", KeyspaceOptions::default()),
+            Err(KvennaError::NamespaceExists("users".to_string()))
+        );
+        for name in ["", "../users", "a/b", "a.kv"] {
+            assert_eq!(
+                kv.create_namespace(name, KeyspaceOptions::default()),
+                Err(KvennaError::InvalidNamespace(name.to_string()))
+            );
+        }
+        kv.namespace_mut("users")
+            .unwrap()
+            .put_string("key", "users")
+            .unwrap();
+
+        assert_eq!(kv.namespaces(), vec!["default", "users"]);
+        assert_eq!(kv.get_string("key"), Ok(Some("default".to_string())));
+        assert_eq!(
+            kv.namespace("users").unwrap().get_string("key"),
+            Ok(Some("users".to_string()))
+        );
+
+        kv.drop_namespace("users").unwrap();
+        assert!(kv.namespace("users").is_err());
+        assert!(kv.drop_namespace(DEFAULT_NAMESPACE).is_err());
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut kv = Kvenna::new();
+        let feed = kv.feed();
+        kv.put_string("a", "1").unwrap();
+        kv.rpush("b", b"x").unwrap();
+        kv.rpop("b").unwrap();
+        kv.put_with_ttl("c", b"3", Some(Duration::from_millis(10)))
+            .unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(kv.purge_expired(), Ok(1));
 
-    pub fn del(&self, key: &str) -> Option<Vec<u8>> {
-        self.skiplist.del(key)
+        let events: Vec<_> = feed
+            .wait(0, |_| true, Duration::ZERO)
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.key, event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("a".to_string(), EventKind::Put),
+                ("b".to_string(), EventKind::Put),
+                ("b".to_string(), EventKind::Delete),
+                ("c".to_string(), EventKind::Put),
+                ("c".to_string(), EventKind::Expire),
+            ]
+        );
     }
 }
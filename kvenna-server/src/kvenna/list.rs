@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+use super::{
+    errors::{KvennaError, Result},
+    keyspace::Keyspace,
+    memory::element_size,
+    value::ValueKind,
+};
+
+// resolve a possibly negative index, counted from the end, against the length of a list
+fn normalize_index(index: isize, len: usize) -> Option<usize> {
+    let index = if index < 0 {
+        len as isize + index
+    } else {
+        index
+    };
+    if index < 0 || index as usize >= len {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+// resolve an inclusive range of possibly negative indexes, return None if it is empty
+pub(super) fn normalize_range(start: isize, stop: isize, len: usize) -> Option<(usize, usize)> {
+    let len = len as isize;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    if start > stop {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+impl Keyspace {
+    fn list(&self, key: &str) -> Result<Option<&VecDeque<Vec<u8>>>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.check_kind(key, ValueKind::List)?;
+        self.touch(key);
+        Ok(self.lists.get(key))
+    }
+
+    fn list_mut(&mut self, key: &str) -> Result<Option<&mut VecDeque<Vec<u8>>>> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::List)?;
+        self.touch(key);
+        Ok(self.lists.get_mut(key))
+    }
+
+    fn push(&mut self, key: &str, value: &[u8], front: bool) -> Result<usize> {
+        self.check_size(key, value.len())?;
+        self.check_persisted(ValueKind::List)?;
+        self.make_room(key, element_size(value.len()))?;
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::List)?;
+        if !self.lists.contains_key(key) {
+            self.reserve(key, None)?;
+        }
+        let list = self.lists.entry(key.to_string()).or_default();
+        if front {
+            list.push_front(value.to_vec());
+        } else {
+            list.push_back(value.to_vec());
+        }
+        let len = list.len();
+        self.grow(key, element_size(value.len()));
+        self.notify_write(key);
+        Ok(len)
+    }
+
+    fn pop(&mut self, key: &str, front: bool) -> Result<Option<Vec<u8>>> {
+        let Some(list) = self.list_mut(key)? else {
+            return Ok(None);
+        };
+        let value = if front {
+            list.pop_front()
+        } else {
+            list.pop_back()
+        };
+        let emptied = list.is_empty();
+        if let Some(value) = value.as_ref() {
+            self.grow(key, -element_size(value.len()));
+        }
+        // empty lists are removed, just like in redis
+        if emptied {
+            self.remove_key(key)?;
+        }
+        self.notify_write(key);
+        Ok(value)
+    }
+
+    // push the value to the head of the list, creating it if it does not exist,
+    // and return the new length
+    pub fn lpush(&mut self, key: &str, value: &[u8]) -> Result<usize> {
+        self.push(key, value, true)
+    }
+
+    // push the value to the tail of the list, creating it if it does not exist,
+    // and return the new length
+    pub fn rpush(&mut self, key: &str, value: &[u8]) -> Result<usize> {
+        self.push(key, value, false)
+    }
+
+    pub fn lpop(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.pop(key, true)
+    }
+
+    pub fn rpop(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.pop(key, false)
+    }
+
+    pub fn llen(&self, key: &str) -> Result<usize> {
+        Ok(self.list(key)?.map_or(0, |list| list.len()))
+    }
+
+    // return the element at index, negative indexes count from the tail
+    pub fn lindex(&self, key: &str, index: isize) -> Result<Option<Vec<u8>>> {
+        let Some(list) = self.list(key)? else {
+            return Ok(None);
+        };
+        Ok(normalize_index(index, list.len()).map(|index| list[index].clone()))
+    }
+
+    // return the elements between start and stop, both inclusive
+    pub fn lrange(&self, key: &str, start: isize, stop: isize) -> Result<Vec<Vec<u8>>> {
+        let Some(list) = self.list(key)? else {
+            return Ok(vec![]);
+        };
+        match normalize_range(start, stop, list.len()) {
+            Some((start, stop)) => Ok(list.range(start..=stop).cloned().collect()),
+            None => Ok(vec![]),
+        }
+    }
+
+    pub fn lset(&mut self, key: &str, index: isize, value: &[u8]) -> Result<()> {
+        self.check_size(key, value.len())?;
+        self.make_room(key, value.len() as isize)?;
+        let Some(list) = self.list_mut(key)? else {
+            return Err(KvennaError::KeyNotFound(key.to_string()));
+        };
+        let index = normalize_index(index, list.len()).ok_or(KvennaError::IndexOutOfRange)?;
+        let old = std::mem::replace(&mut list[index], value.to_vec());
+        self.grow(key, value.len() as isize - old.len() as isize);
+        self.notify_write(key);
+        Ok(())
+    }
+
+    // keep only the elements between start and stop, both inclusive
+    pub fn ltrim(&mut self, key: &str, start: isize, stop: isize) -> Result<()> {
+        let Some(list) = self.list_mut(key)? else {
+            return Ok(());
+        };
+        match normalize_range(start, stop, list.len()) {
+            Some((start, stop)) => {
+                let size = |value: Vec<u8>| element_size(value.len());
+                let removed: isize = list.drain(stop + 1..).map(size).sum::<isize>()
+                    + list.drain(..start).map(size).sum::<isize>();
+                self.grow(key, -removed);
+            }
+            None => self.remove_key(key)?,
+        }
+        self.notify_write(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list() {
+        let mut ks = Keyspace::default();
+        assert_eq!(ks.rpush("queue", b"b"), Ok(1));
+        assert_eq!(ks.rpush("queue", b"c"), Ok(2));
+        assert_eq!(ks.lpush("queue", b"a"), Ok(3));
+        assert_eq!(
+            ks.lrange("queue", 0, -1),
+            Ok(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()])
+        );
+        assert_eq!(ks.lindex("queue", -1), Ok(Some(b"c".to_vec())));
+        assert_eq!(ks.lindex("queue", 3), Ok(None));
+
+        ks.lset("queue", 1, b"B").unwrap();
+        assert_eq!(ks.lset("queue", 5, b"x"), Err(KvennaError::IndexOutOfRange));
+        ks.ltrim("queue", 1, 5).unwrap();
+        assert_eq!(
+            ks.lrange("queue", 0, -1),
+            Ok(vec![b"B".to_vec(), b"c".to_vec()])
+        );
+
+        assert_eq!(ks.lpop("queue"), Ok(Some(b"B".to_vec())));
+        assert_eq!(ks.rpop("queue"), Ok(Some(b"c".to_vec())));
+        assert_eq!(ks.llen("queue"), Ok(0));
+        assert!(!ks.contains("queue"));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut ks = Keyspace::default();
+        ks.put_string("name", "kvenna").unwrap();
+        assert_eq!(ks.lpush("name", b"a"), Err(KvennaError::WrongType));
+        assert_eq!(ks.llen("name"), Err(KvennaError::WrongType));
+
+        ks.rpush("list", b"a").unwrap();
+        assert_eq!(ks.append("list", b"a"), Err(KvennaError::WrongType));
+        // put overwrites any kind of value
+        ks.put_string("list", "a").unwrap();
+        assert_eq!(ks.get_string("list"), Ok(Some("a".to_string())));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rand::Rng;
+
+use super::{
+    errors::{KvennaError, Result},
+    keyspace::Keyspace,
+    watch::EventKind,
+};
+
+// approximate bookkeeping cost of a key, and of an element of a collection
+pub(super) const KEY_OVERHEAD: usize = 64;
+pub(super) const ELEMENT_OVERHEAD: usize = 16;
+// how many keys the lru and lfu evictions compare, like redis' maxmemory-samples
+const EVICTION_SAMPLES: usize = 5;
+
+// the approximate memory taken by an element of `len` bytes
+pub(super) fn element_size(len: usize) -> isize {
+    (len + ELEMENT_OVERHEAD) as isize
+}
+
+// EvictionPolicy decides which keys are evicted once a keyspace uses more memory
+// than its max_memory option
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum EvictionPolicy {
+    // reject the writes instead
+    #[default]
+    NoEviction,
+    // evict the least recently used key
+    AllKeysLru,
+    // evict the least frequently used key
+    AllKeysLfu,
+    // evict the key with a ttl which expires first
+    VolatileTtl,
+    // evict any key
+    Random,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "noeviction" => Ok(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Ok(EvictionPolicy::AllKeysLru),
+            "allkeys-lfu" => Ok(EvictionPolicy::AllKeysLfu),
+            "volatile-ttl" => Ok(EvictionPolicy::VolatileTtl),
+            "random" => Ok(EvictionPolicy::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+// Usage is the approximate memory taken by a key and how it has been accessed,
+// the access is recorded by concurrent reads too, hence the atomics
+#[derive(Debug)]
+pub(super) struct Usage {
+    size: usize,
+    // index of the key in the tracked keys
+    slot: usize,
+    last_access: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl Keyspace {
+    // the approximate memory taken by the keys and their values in bytes
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    // record an access to the key
+    pub(super) fn touch(&self, key: &str) {
+        if let Some(usage) = self.usage.get(key) {
+            // the accesses only need to be roughly ordered
+            let clock = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+            usage.last_access.store(clock, Ordering::Relaxed);
+            usage.hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // account for the value of the key growing, or shrinking, by delta bytes
+    pub(super) fn grow(&mut self, key: &str, delta: isize) {
+        if !self.usage.contains_key(key) {
+            self.usage.insert(
+                key.to_string(),
+                Usage {
+                    size: key.len() + KEY_OVERHEAD,
+                    slot: self.tracked.len(),
+                    last_access: AtomicU64::new(0),
+                    hits: AtomicU64::new(0),
+                },
+            );
+            self.tracked.push(key.to_string());
+            self.used_memory += key.len() + KEY_OVERHEAD;
+        }
+        let usage = self.usage.get_mut(key).unwrap();
+        let size = usage.size.saturating_add_signed(delta);
+        self.used_memory = self.used_memory - usage.size + size;
+        usage.size = size;
+        self.touch(key);
+    }
+
+    pub(super) fn untrack(&mut self, key: &str) {
+        if let Some(usage) = self.usage.remove(key) {
+            self.used_memory -= usage.size;
+            self.tracked.swap_remove(usage.slot);
+            if let Some(moved) = self.tracked.get(usage.slot) {
+                self.usage.get_mut(moved).unwrap().slot = usage.slot;
+            }
+        }
+    }
+
+    // evict keys until the value of the key can grow by delta bytes within max_memory,
+    // called before the writes which may take more memory. The key itself is never
+    // evicted, its writer has read its value already. Fails with OutOfMemory if there is
+    // nothing left to evict under the eviction policy.
+    pub(super) fn make_room(&mut self, key: &str, delta: isize) -> Result<()> {
+        let Some(max_memory) = self.options.max_memory else {
+            return Ok(());
+        };
+        let mut incoming = delta.max(0) as usize;
+        if !self.usage.contains_key(key) {
+            incoming += key.len() + KEY_OVERHEAD;
+        }
+        // evicting everything would not be enough
+        let Some(limit) = max_memory.checked_sub(incoming) else {
+            return Err(KvennaError::OutOfMemory);
+        };
+        if self.used_memory <= limit {
+            return Ok(());
+        }
+        self.purge_expired()?;
+        while self.used_memory > limit {
+            let victim = self
+                .eviction_candidate(key)
+                .ok_or(KvennaError::OutOfMemory)?;
+            self.remove_key(&victim)?;
+            self.notify(&victim, EventKind::Evict);
+        }
+        Ok(())
+    }
+
+    // a key picked at random other than except, the last slot standing in for the one
+    // of except
+    fn random_key(&self, except: &str) -> Option<&String> {
+        let slot = self.usage.get(except).map(|usage| usage.slot);
+        let len = self.tracked.len() - slot.is_some() as usize;
+        if len == 0 {
+            return None;
+        }
+        let i = rand::thread_rng().gen_range(0..len);
+        Some(&self.tracked[if Some(i) == slot { len } else { i }])
+    }
+
+    // the keys other than except the lru and lfu evictions choose from, all of them in a
+    // small keyspace, otherwise a few picked at random as redis does
+    fn eviction_samples(&self, except: &str) -> Vec<&String> {
+        if self.tracked.len() <= EVICTION_SAMPLES {
+            return self.tracked.iter().filter(|key| *key != except).collect();
+        }
+        (0..EVICTION_SAMPLES)
+            .filter_map(|_| self.random_key(except))
+            .collect()
+    }
+
+    fn eviction_candidate(&self, except: &str) -> Option<String> {
+        let samples = || {
+            self.eviction_samples(except)
+                .into_iter()
+                .map(|key| (key, &self.usage[key]))
+        };
+        let candidate = match self.options.eviction {
+            EvictionPolicy::NoEviction => None,
+            EvictionPolicy::AllKeysLru => {
+                samples().min_by_key(|(_, usage)| usage.last_access.load(Ordering::Relaxed))
+            }
+            EvictionPolicy::AllKeysLfu => samples().min_by_key(|(_, usage)| {
+                (
+                    usage.hits.load(Ordering::Relaxed),
+                    usage.last_access.load(Ordering::Relaxed),
+                )
+            }),
+            EvictionPolicy::VolatileTtl => {
+                return self
+                    .expiry_order
+                    .iter()
+                    .map(|(_, key)| key)
+                    .find(|key| *key != except)
+                    .cloned()
+            }
+            EvictionPolicy::Random => return self.random_key(except).cloned(),
+        };
+        candidate.map(|(key, _)| key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{super::keyspace::KeyspaceOptions, *};
+
+    fn keyspace(eviction: EvictionPolicy) -> Keyspace {
+        Keyspace::new(KeyspaceOptions {
+            max_memory: Some(4 * (KEY_OVERHEAD + 101)),
+            eviction,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_eviction() {
+        let value = [0; 100];
+        let mut ks = keyspace(EvictionPolicy::NoEviction);
+        for key in ["a", "b", "c", "d"] {
+            ks.put(key, &value).unwrap();
+        }
+        assert_eq!(ks.put("e", &value), Err(KvennaError::OutOfMemory));
+        // removing keys is always allowed
+        assert_eq!(ks.remove("a"), Ok(true));
+        ks.put("e", &value).unwrap();
+
+        let mut ks = keyspace(EvictionPolicy::AllKeysLru);
+        for key in ["a", "b", "c", "d"] {
+            ks.put(key, &value).unwrap();
+        }
+        ks.get("a").unwrap();
+        ks.put("e", &value).unwrap();
+        assert!(ks.contains("a"));
+        assert!(!ks.contains("b"));
+
+        let mut ks = keyspace(EvictionPolicy::AllKeysLfu);
+        for key in ["a", "b", "c", "d"] {
+            ks.put(key, &value).unwrap();
+            ks.get(key).unwrap();
+        }
+        ks.get("a").unwrap();
+        ks.get("b").unwrap();
+        ks.get("d").unwrap();
+        ks.put("e", &value).unwrap();
+        assert!(!ks.contains("c"));
+
+        let mut ks = keyspace(EvictionPolicy::VolatileTtl);
+        ks.put("a", &value).unwrap();
+        ks.put_with_ttl("b", &value, Some(Duration::from_secs(10)))
+            .unwrap();
+        ks.put_with_ttl("c", &value, Some(Duration::from_secs(5)))
+            .unwrap();
+        ks.put("d", &value).unwrap();
+        ks.put("e", &value).unwrap();
+        assert!(!ks.contains("c"));
+        ks.put("f", &value).unwrap();
+        assert!(!ks.contains("b"));
+        assert_eq!(ks.put("g", &value), Err(KvennaError::OutOfMemory));
+
+        // writes make room for themselves, and those which can never fit evict nothing
+        let mut ks = keyspace(EvictionPolicy::AllKeysLru);
+        for i in 0..20 {
+            ks.put(&i.to_string(), &value).unwrap();
+            assert!(ks.used_memory() <= ks.options.max_memory.unwrap());
+        }
+        let len = ks.len();
+        assert_eq!(ks.put("big", &[0; 1000]), Err(KvennaError::OutOfMemory));
+        assert_eq!(ks.len(), len);
+    }
+
+    #[test]
+    fn test_evict_written_key() {
+        let value = [1; 100];
+        for eviction in [EvictionPolicy::AllKeysLfu, EvictionPolicy::Random] {
+            let mut ks = keyspace(eviction);
+            for key in ["a", "b", "c", "d"] {
+                ks.put(key, &value).unwrap();
+            }
+            // the key written to is the least used one
+            for _ in 0..3 {
+                for key in ["b", "c", "d"] {
+                    ks.get(key).unwrap();
+                }
+            }
+            assert_eq!(ks.append("a", &[2; 50]), Ok(150));
+            let appended = ks.get("a").unwrap().unwrap();
+            assert_eq!((appended[0], appended[149]), (1, 2));
+            assert_eq!(ks.set_range("a", 180, &[3; 20]), Ok(200));
+            assert_eq!(ks.get_range("a", 0, 2).unwrap(), Some(vec![1, 1]));
+            let used: usize = ["a", "b", "c", "d"]
+                .iter()
+                .filter(|key| ks.contains(key))
+                .map(|key| KEY_OVERHEAD + key.len() + ks.strlen(key).unwrap())
+                .sum();
+            assert_eq!(ks.used_memory(), used);
+        }
+    }
+
+    #[test]
+    fn test_used_memory() {
+        let mut ks = Keyspace::default();
+        ks.put("key", b"value").unwrap();
+        ks.append("key", b"!").unwrap();
+        assert_eq!(ks.used_memory(), KEY_OVERHEAD + 3 + 6);
+        ks.rpush("list", b"a").unwrap();
+        ks.rpush("list", b"b").unwrap();
+        ks.rpop("list").unwrap();
+        ks.hset("hash", "f", b"v").unwrap();
+        ks.del("key").unwrap();
+        ks.del("list").unwrap();
+        ks.del("hash").unwrap();
+        assert_eq!(ks.used_memory(), 0);
+    }
+}
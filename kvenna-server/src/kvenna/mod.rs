@@ -1,3 +1,20 @@
+pub mod engine;
+pub mod errors;
+pub mod hash;
+pub mod keyspace;
+#[allow(clippy::module_inception)]
 pub mod kvenna;
+pub mod list;
+pub mod memory;
+pub mod pubsub;
+pub mod set;
+pub mod sharded;
+pub mod stream;
+pub mod value;
+pub mod watch;
+pub mod zset;
 
+pub use errors::KvennaError;
+pub use keyspace::{Keyspace, KeyspaceOptions};
 pub use kvenna::Kvenna;
+pub use sharded::ShardedKvenna;
@@ -0,0 +1,282 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+// messages waiting for a subscriber, beyond them the subscriber is too slow to keep up
+// and is dropped
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    // the pattern the channel matched, None for the exact subscriptions
+    pub pattern: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+struct Subscriber {
+    channels: HashSet<String>,
+    patterns: HashSet<String>,
+    sender: SyncSender<Message>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: u64,
+    subscribers: HashMap<u64, Subscriber>,
+}
+
+// PubSub delivers the messages published to a channel to the subscribers of the channel
+// and of the patterns matching it. Channels have nothing to do with keys, and messages
+// published to a channel without subscribers are dropped. A subscriber which lets
+// SUBSCRIBER_BUFFER messages pile up is unsubscribed, rather than holding them all.
+#[derive(Default)]
+pub struct PubSub {
+    inner: Arc<Mutex<Subscribers>>,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // publish the message and return the number of subscriptions it was delivered to,
+    // publishing never waits for the subscribers
+    pub fn publish(&self, channel: &str, payload: &[u8]) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let mut delivered = 0;
+        let mut gone = vec![];
+        for (id, subscriber) in inner.subscribers.iter() {
+            let mut messages = vec![];
+            if subscriber.channels.contains(channel) {
+                messages.push(None);
+            }
+            for pattern in subscriber.patterns.iter() {
+                if glob_match(pattern, channel) {
+                    messages.push(Some(pattern.clone()));
+                }
+            }
+            for pattern in messages {
+                let message = Message {
+                    channel: channel.to_string(),
+                    pattern,
+                    payload: payload.to_vec(),
+                };
+                match subscriber.sender.try_send(message) {
+                    Ok(()) => delivered += 1,
+                    Err(TrySendError::Full(_)) => {
+                        println!("[PUBSUB] dropped subscriber {} falling behind", id);
+                        gone.push(*id);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        gone.push(*id);
+                        break;
+                    }
+                }
+            }
+        }
+        for id in gone {
+            inner.subscribers.remove(&id);
+        }
+        delivered
+    }
+
+    // subscribe to the exact channels and to the channels matching the glob patterns,
+    // the subscription ends when the returned handle is dropped
+    pub fn subscribe(&self, channels: &[&str], patterns: &[&str]) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BUFFER);
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.subscribers.insert(
+            id,
+            Subscriber {
+                channels: channels.iter().map(|c| c.to_string()).collect(),
+                patterns: patterns.iter().map(|p| p.to_string()).collect(),
+                sender,
+            },
+        );
+        Subscription {
+            id,
+            receiver,
+            inner: self.inner.clone(),
+        }
+    }
+
+    // number of subscriptions to the channel, pattern subscriptions excluded
+    pub fn subscribers(&self, channel: &str) -> usize {
+        self.inner
+            .lock()
+            .unwrap()
+            .subscribers
+            .values()
+            .filter(|subscriber| subscriber.channels.contains(channel))
+            .count()
+    }
+}
+
+pub struct Subscription {
+    id: u64,
+    receiver: Receiver<Message>,
+    inner: Arc<Mutex<Subscribers>>,
+}
+
+impl Subscription {
+    // wait up to timeout for the next message, fails with Disconnected once the
+    // subscriber was dropped for falling behind and the messages it held are read
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Message, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.inner.lock().unwrap().subscribers.remove(&self.id);
+    }
+}
+
+// match the text against a glob pattern: `*` matches any sequence, `?` any character,
+// `[abc]`, `[a-z]` and `[^abc]` a character of the class, and `\` escapes the next one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let text: Vec<_> = text.chars().collect();
+    // positions to retry from when the last `*` has to match one more character
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let matched = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match_class(&pattern, p, text[t]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(p + 2),
+            Some(c) => (*c == text[t]).then_some(p + 1),
+            None => None,
+        };
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star, from))) => {
+                backtrack = Some((star, from + 1));
+                p = star + 1;
+                t = from + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// match the character against the class starting at pattern[start] == '[', and return
+// the position after the class if it matches
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start + 1;
+    let negated = pattern.get(i) == Some(&'^');
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    while i < pattern.len() && pattern[i] != ']' {
+        if pattern[i] == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            matched |= pattern[i] == c;
+            i += 1;
+        } else if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            let (low, high) = if pattern[i] <= pattern[i + 2] {
+                (pattern[i], pattern[i + 2])
+            } else {
+                (pattern[i + 2], pattern[i])
+            };
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    // an unclosed class is not a class, match the `[` literally
+    if i >= pattern.len() {
+        return (c == '[').then_some(start + 1);
+    }
+    (matched != negated).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("news.*", "news.sport"));
+        assert!(glob_match("news.*", "news."));
+        assert!(!glob_match("news.*", "weather"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-c]llo", "hbllo"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("\\*", "*"));
+        assert!(!glob_match("\\*", "a"));
+        assert!(glob_match("[", "["));
+    }
+
+    #[test]
+    fn test_publish() {
+        let pubsub = PubSub::new();
+        let exact = pubsub.subscribe(&["news.sport"], &[]);
+        let pattern = pubsub.subscribe(&[], &["news.*"]);
+        assert_eq!(pubsub.publish("news.sport", b"goal"), 2);
+        assert_eq!(pubsub.publish("news.tech", b"rust"), 1);
+        assert_eq!(pubsub.publish("weather", b"rain"), 0);
+
+        let timeout = Duration::from_millis(10);
+        let message = exact.recv_timeout(timeout).unwrap();
+        assert_eq!(
+            (message.channel.as_str(), message.pattern),
+            ("news.sport", None)
+        );
+        assert_eq!(exact.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+        let message = pattern.recv_timeout(timeout).unwrap();
+        assert_eq!(message.pattern.as_deref(), Some("news.*"));
+        assert_eq!(pattern.recv_timeout(timeout).unwrap().payload, b"rust");
+
+        drop(exact);
+        assert_eq!(pubsub.subscribers("news.sport"), 0);
+        assert_eq!(pubsub.publish("news.sport", b"goal"), 1);
+    }
+
+    #[test]
+    fn test_slow_subscriber() {
+        let pubsub = PubSub::new();
+        let slow = pubsub.subscribe(&["news"], &[]);
+        for _ in 0..SUBSCRIBER_BUFFER {
+            assert_eq!(pubsub.publish("news", b"goal"), 1);
+        }
+        assert_eq!(pubsub.publish("news", b"goal"), 0);
+        assert_eq!(pubsub.subscribers("news"), 0);
+
+        // the buffered messages are still read, then the subscription ends
+        let timeout = Duration::from_millis(10);
+        for _ in 0..SUBSCRIBER_BUFFER {
+            assert!(slow.recv_timeout(timeout).is_ok());
+        }
+        assert_eq!(
+            slow.recv_timeout(timeout),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,259 @@
+#![allow(dead_code)]
+
+use std::{collections::HashSet, str::FromStr};
+
+use rand::seq::IteratorRandom;
+
+use super::{errors::Result, keyspace::Keyspace, memory::element_size, value::ValueKind};
+
+type Set = HashSet<String>;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+impl FromStr for SetOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(SetOp::Union),
+            "inter" => Ok(SetOp::Inter),
+            "diff" => Ok(SetOp::Diff),
+            _ => Err(()),
+        }
+    }
+}
+
+// combine the sets, the difference is the members of the first set which are in none
+// of the others
+pub(super) fn combine(op: SetOp, sets: &[&Set]) -> Vec<String> {
+    let Some((first, rest)) = sets.split_first() else {
+        return vec![];
+    };
+    let mut members: Vec<_> = first
+        .iter()
+        .filter(|member| match op {
+            SetOp::Union => true,
+            SetOp::Inter => rest.iter().all(|set| set.contains(*member)),
+            SetOp::Diff => rest.iter().all(|set| !set.contains(*member)),
+        })
+        .cloned()
+        .collect();
+    if op == SetOp::Union {
+        let mut union: Set = members.into_iter().collect();
+        union.extend(rest.iter().flat_map(|set| set.iter().cloned()));
+        members = union.into_iter().collect();
+    }
+    members.sort();
+    members
+}
+
+impl Keyspace {
+    fn set(&self, key: &str) -> Result<Option<&Set>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.check_kind(key, ValueKind::Set)?;
+        self.touch(key);
+        Ok(self.sets.get(key))
+    }
+
+    fn set_mut(&mut self, key: &str) -> Result<Option<&mut Set>> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Set)?;
+        self.touch(key);
+        Ok(self.sets.get_mut(key))
+    }
+
+    // add the member to the set, creating it if it does not exist,
+    // return true if the member is new
+    pub fn sadd(&mut self, key: &str, member: &str) -> Result<bool> {
+        self.check_size(key, member.len())?;
+        self.check_persisted(ValueKind::Set)?;
+        self.make_room(key, element_size(member.len()))?;
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Set)?;
+        if !self.sets.contains_key(key) {
+            self.reserve(key, None)?;
+        }
+        let set = self.sets.entry(key.to_string()).or_default();
+        let added = set.insert(member.to_string());
+        if added {
+            self.grow(key, element_size(member.len()));
+            self.notify_write(key);
+        }
+        Ok(added)
+    }
+
+    // remove the member, return false if it is not a member
+    pub fn srem(&mut self, key: &str, member: &str) -> Result<bool> {
+        let Some(set) = self.set_mut(key)? else {
+            return Ok(false);
+        };
+        let removed = set.remove(member);
+        let emptied = set.is_empty();
+        if removed {
+            self.grow(key, -element_size(member.len()));
+        }
+        // empty sets are removed, just like in redis
+        if emptied {
+            self.remove_key(key)?;
+        }
+        if removed {
+            self.notify_write(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn sismember(&self, key: &str, member: &str) -> Result<bool> {
+        Ok(self.set(key)?.is_some_and(|set| set.contains(member)))
+    }
+
+    // return all the members of the set in order
+    pub fn smembers(&self, key: &str) -> Result<Vec<String>> {
+        let mut members: Vec<_> = self.set(key)?.into_iter().flatten().cloned().collect();
+        members.sort();
+        Ok(members)
+    }
+
+    pub fn scard(&self, key: &str) -> Result<usize> {
+        Ok(self.set(key)?.map_or(0, |set| set.len()))
+    }
+
+    // remove and return a random member
+    pub fn spop(&mut self, key: &str) -> Result<Option<String>> {
+        let Some(member) = self.srandmember(key)? else {
+            return Ok(None);
+        };
+        self.srem(key, &member)?;
+        Ok(Some(member))
+    }
+
+    // return a random member without removing it
+    pub fn srandmember(&self, key: &str) -> Result<Option<String>> {
+        let mut rng = rand::thread_rng();
+        Ok(self
+            .set(key)?
+            .and_then(|set| set.iter().choose(&mut rng).cloned()))
+    }
+
+    // combine the sets stored at keys, a missing key counts as an empty set
+    pub fn sop(&self, op: SetOp, keys: &[&str]) -> Result<Vec<String>> {
+        let empty = Set::new();
+        let mut sets = vec![];
+        for key in keys {
+            sets.push(self.set(key)?.unwrap_or(&empty));
+        }
+        Ok(combine(op, &sets))
+    }
+
+    pub fn sunion(&self, keys: &[&str]) -> Result<Vec<String>> {
+        self.sop(SetOp::Union, keys)
+    }
+
+    pub fn sinter(&self, keys: &[&str]) -> Result<Vec<String>> {
+        self.sop(SetOp::Inter, keys)
+    }
+
+    pub fn sdiff(&self, keys: &[&str]) -> Result<Vec<String>> {
+        self.sop(SetOp::Diff, keys)
+    }
+
+    // store the combination of the sets at dest, overwriting whatever it holds,
+    // and return the number of members stored
+    pub fn sopstore(&mut self, op: SetOp, dest: &str, keys: &[&str]) -> Result<usize> {
+        let members = self.sop(op, keys)?;
+        self.store_set(dest, members)
+    }
+
+    // store the members as a set at dest, overwriting whatever it holds,
+    // and return the number of members stored
+    pub fn store_set(&mut self, dest: &str, members: Vec<String>) -> Result<usize> {
+        self.check_size(dest, 0)?;
+        self.check_persisted(ValueKind::Set)?;
+        let size = members
+            .iter()
+            .map(|member| element_size(member.len()))
+            .sum();
+        self.make_room(dest, size)?;
+        self.purge_if_expired(dest)?;
+        let existed = self.contains(dest);
+        if existed {
+            self.remove_key(dest)?;
+        }
+        if !members.is_empty() {
+            self.reserve(dest, None)?;
+            self.sets
+                .insert(dest.to_string(), members.iter().cloned().collect());
+            self.grow(dest, size);
+        }
+        if existed || !members.is_empty() {
+            self.notify_write(dest);
+        }
+        Ok(members.len())
+    }
+
+    pub fn sunionstore(&mut self, dest: &str, keys: &[&str]) -> Result<usize> {
+        self.sopstore(SetOp::Union, dest, keys)
+    }
+
+    pub fn sinterstore(&mut self, dest: &str, keys: &[&str]) -> Result<usize> {
+        self.sopstore(SetOp::Inter, dest, keys)
+    }
+
+    pub fn sdiffstore(&mut self, dest: &str, keys: &[&str]) -> Result<usize> {
+        self.sopstore(SetOp::Diff, dest, keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kvenna::KvennaError;
+
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut ks = Keyspace::default();
+        assert_eq!(ks.sadd("tags", "rust"), Ok(true));
+        assert_eq!(ks.sadd("tags", "rust"), Ok(false));
+        ks.sadd("tags", "kv").unwrap();
+        assert_eq!(ks.sismember("tags", "kv"), Ok(true));
+        assert_eq!(ks.scard("tags"), Ok(2));
+        assert_eq!(
+            ks.smembers("tags"),
+            Ok(vec!["kv".to_string(), "rust".to_string()])
+        );
+
+        let member = ks.spop("tags").unwrap().unwrap();
+        assert_eq!(ks.sismember("tags", &member), Ok(false));
+        assert_eq!(ks.scard("tags"), Ok(1));
+        assert!(ks.srandmember("tags").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let mut ks = Keyspace::default();
+        for member in ["a", "b", "c"] {
+            ks.sadd("x", member).unwrap();
+        }
+        for member in ["b", "c", "d"] {
+            ks.sadd("y", member).unwrap();
+        }
+        let strings =
+            |members: &[&str]| -> Vec<String> { members.iter().map(|m| m.to_string()).collect() };
+        assert_eq!(ks.sunion(&["x", "y"]), Ok(strings(&["a", "b", "c", "d"])));
+        assert_eq!(ks.sinter(&["x", "y", "missing"]), Ok(vec![]));
+        assert_eq!(ks.sdiff(&["x", "y"]), Ok(strings(&["a"])));
+
+        ks.put_string("dest", "overwritten").unwrap();
+        assert_eq!(ks.sinterstore("dest", &["x", "y"]), Ok(2));
+        assert_eq!(ks.smembers("dest"), Ok(strings(&["b", "c"])));
+        ks.put_string("str", "a").unwrap();
+        assert_eq!(ks.sunion(&["x", "str"]), Err(KvennaError::WrongType));
+    }
+}
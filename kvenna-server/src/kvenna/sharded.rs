@@ -0,0 +1,375 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet, VecDeque},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    engine::Backend,
+    errors::{KvennaError, Result},
+    keyspace::KeyspaceOptions,
+    kvenna::Kvenna,
+    pubsub::PubSub,
+    set::{combine, SetOp},
+    watch::ChangeFeed,
+};
+
+// ShardedKvenna partitions the keys across independent Kvenna shards by key hash, each
+// behind its own lock, so that requests on keys of different shards run in parallel.
+// Every shard hosts all the namespaces, with their limits split evenly between the
+// shards and enforced by each shard on its own, and the shards share one change feed, each publishing to its own stripe of
+// it, and one set of channels.
+pub struct ShardedKvenna {
+    shards: Vec<RwLock<Kvenna>>,
+    feed: Arc<ChangeFeed>,
+    pubsub: Arc<PubSub>,
+}
+
+// a scan copies the entries of a shard out a batch at a time, of up to this many
+// entries or this many bytes
+const SCAN_BATCH: usize = 64;
+const SCAN_BATCH_BYTES: usize = 1024 * 1024;
+
+// the entries of a shard read by a scan and not handed out yet, and the key the next
+// batch starts from, None once the shard has no more
+struct Run {
+    batch: VecDeque<(String, Vec<u8>)>,
+    next: Option<String>,
+}
+
+// the shard placement has to be stable across runs for the persistent backends,
+// so the keys are hashed with fnv-1a rather than the randomly seeded std hasher
+fn fnv1a(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// the limits of a shard, rounded down so that the shards together keep to the options.
+// A shard evicts, or refuses writes, once its own share is used, so a value larger than
+// the memory share could never be stored: it becomes the value size limit if none is
+// given, and a larger limit is refused.
+fn split_options(options: &KeyspaceOptions, n: usize) -> Result<KeyspaceOptions> {
+    let mut split = options.clone();
+    if let Some(max_keys) = options.max_keys {
+        if max_keys < n {
+            return Err(KvennaError::InvalidOptions(format!(
+                "max_keys {} is less than one key for each of the {} shards",
+                max_keys, n
+            )));
+        }
+        split.max_keys = Some(max_keys / n);
+    }
+    if let Some(max_memory) = options.max_memory {
+        let share = max_memory / n;
+        match options.max_value_size {
+            Some(max_value_size) if max_value_size > share => {
+                return Err(KvennaError::InvalidOptions(format!(
+                    "max_value_size {} is larger than the {} bytes of max_memory of each of \
+                     the {} shards",
+                    max_value_size, share, n
+                )))
+            }
+            Some(_) => {}
+            None => split.max_value_size = Some(share),
+        }
+        split.max_memory = Some(share);
+    }
+    Ok(split)
+}
+
+impl ShardedKvenna {
+    // create n in-memory shards
+    pub fn new(n: usize) -> Self {
+        // opening the skiplist engine never fails
+        Self::open(Backend::SkipList, KeyspaceOptions::default(), n).unwrap()
+    }
+
+    // open n shards stored by the backend, the options of the default namespace are
+    // split between them
+    pub fn open(backend: Backend, options: KeyspaceOptions, n: usize) -> Result<Self> {
+        let n = n.max(1);
+        let (feed, pubsub) = (
+            Arc::new(ChangeFeed::with_stripes(n)),
+            Arc::new(PubSub::new()),
+        );
+        let options = split_options(&options, n)?;
+        backend.check_shards(n)?;
+        let mut shards = Vec::with_capacity(n);
+        for i in 0..n {
+            let shard = Kvenna::open_shared(
+                backend.shard(i, n),
+                options.clone(),
+                feed.clone(),
+                i,
+                pubsub.clone(),
+            )?;
+            shards.push(RwLock::new(shard));
+        }
+        Ok(Self {
+            shards,
+            feed,
+            pubsub,
+        })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<Kvenna> {
+        &self.shards[(fnv1a(key) % self.shards.len() as u64) as usize]
+    }
+
+    // lock the shard holding the key for reading
+    pub fn read(&self, key: &str) -> RwLockReadGuard<'_, Kvenna> {
+        self.shard(key).read().unwrap()
+    }
+
+    // lock the shard holding the key for writing
+    pub fn write(&self, key: &str) -> RwLockWriteGuard<'_, Kvenna> {
+        self.shard(key).write().unwrap()
+    }
+
+    // lock every shard for reading in turn
+    pub fn shards(&self) -> impl Iterator<Item = RwLockReadGuard<'_, Kvenna>> {
+        self.shards.iter().map(|shard| shard.read().unwrap())
+    }
+
+    pub fn feed(&self) -> Arc<ChangeFeed> {
+        self.feed.clone()
+    }
+
+    pub fn pubsub(&self) -> Arc<PubSub> {
+        self.pubsub.clone()
+    }
+
+    // all the shards host the same namespaces
+    pub fn namespaces(&self) -> Vec<String> {
+        self.shards[0].read().unwrap().namespaces()
+    }
+
+    // create the namespace on every shard, the shards are locked all along so that
+    // no request sees it on some of them only
+    pub fn create_namespace(&self, name: &str, options: KeyspaceOptions) -> Result<()> {
+        let mut shards: Vec<_> = self.shards.iter().map(|s| s.write().unwrap()).collect();
+        let options = split_options(&options, shards.len())?;
+        for i in 0..shards.len() {
+            if let Err(err) = shards[i].create_namespace(name, options.clone()) {
+                // undo the shards where it was created
+                if !matches!(err, KvennaError::NamespaceExists(_)) {
+                    for shard in shards[..i].iter_mut() {
+                        let _ = shard.drop_namespace(name);
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // drop the namespace from every shard, and return the first error
+    pub fn drop_namespace(&self, name: &str) -> Result<()> {
+        let mut shards: Vec<_> = self.shards.iter().map(|s| s.write().unwrap()).collect();
+        let mut result = Ok(());
+        for shard in shards.iter_mut() {
+            if let Err(err) = shard.drop_namespace(name) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    // remove expired keys of all shards, locking one shard at a time, and return the
+    // number of removed keys
+    pub fn purge_expired(&self) -> Result<usize> {
+        let mut purged = 0;
+        for shard in self.shards.iter() {
+            purged += shard.write().unwrap().purge_expired()?;
+        }
+        Ok(purged)
+    }
+
+    // return in key order the string values of the namespace whose key is in
+    // [start, end), gathered from every shard
+    pub fn scan(
+        &self,
+        namespace: &str,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut entries = vec![];
+        self.scan_each(namespace, start, end, |key, value| {
+            entries.push((key, value));
+            true
+        })?;
+        Ok(entries)
+    }
+
+    // like scan, but the entries are handed to f in key order as they are read from
+    // the shards. They are copied out a batch at a time, each shard being locked only
+    // while its batch is read, so f may be slow without holding up the writers, and
+    // it sees the writes made meanwhile to the keys it has not reached yet. f returns
+    // false to stop.
+    pub fn scan_each<F>(
+        &self,
+        namespace: &str,
+        start: &str,
+        end: Option<&str>,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(String, Vec<u8>) -> bool,
+    {
+        let mut runs: Vec<_> = (0..self.shards.len())
+            .map(|_| Run {
+                batch: VecDeque::new(),
+                next: Some(start.to_owned()),
+            })
+            .collect();
+        // the next entry of every run, the one with the lowest key goes first
+        let mut heads = BinaryHeap::new();
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some((key, value)) = self.next_entry(i, run, namespace, end)? {
+                heads.push(Reverse((key, i, value)));
+            }
+        }
+        while let Some(Reverse((key, i, value))) = heads.pop() {
+            if !f(key, value) {
+                break;
+            }
+            if let Some((key, value)) = self.next_entry(i, &mut runs[i], namespace, end)? {
+                heads.push(Reverse((key, i, value)));
+            }
+        }
+        Ok(())
+    }
+
+    // the next entry of the run of shard i, reading its next batch if it is empty
+    fn next_entry(
+        &self,
+        i: usize,
+        run: &mut Run,
+        namespace: &str,
+        end: Option<&str>,
+    ) -> Result<Option<(String, Vec<u8>)>> {
+        if run.batch.is_empty() {
+            if let Some(start) = run.next.take() {
+                let shard = self.shards[i].read().unwrap();
+                let mut entries = shard.namespace(namespace)?.scan_iter(&start, end);
+                let mut bytes = 0;
+                while run.batch.len() < SCAN_BATCH && bytes < SCAN_BATCH_BYTES {
+                    let Some(entry) = entries.next() else {
+                        break;
+                    };
+                    let (key, value) = entry?;
+                    bytes += key.len() + value.len();
+                    run.batch.push_back((key, value));
+                }
+                // a full batch goes on from right after its last key
+                if run.batch.len() == SCAN_BATCH || bytes >= SCAN_BATCH_BYTES {
+                    run.next = run.batch.back().map(|(key, _)| format!("{}\0", key));
+                }
+            }
+        }
+        Ok(run.batch.pop_front())
+    }
+
+    // combine the sets stored at keys of any shard, each set is read under the lock
+    // of its own shard
+    pub fn sop(&self, op: SetOp, keys: &[&str]) -> Result<Vec<String>> {
+        let mut sets = vec![];
+        for key in keys {
+            let members = self.read(key).smembers(key)?;
+            sets.push(members.into_iter().collect::<HashSet<_>>());
+        }
+        let sets: Vec<_> = sets.iter().collect();
+        Ok(combine(op, &sets))
+    }
+
+    // store the combination of the sets at dest, and return the number of members stored
+    pub fn sopstore(&self, op: SetOp, dest: &str, keys: &[&str]) -> Result<usize> {
+        let members = self.sop(op, keys)?;
+        self.write(dest).store_set(dest, members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded() {
+        let kv = ShardedKvenna::new(4);
+        for i in (0..20).rev() {
+            let key = format!("key{:02}", i);
+            kv.write(&key).put_string(&key, &i.to_string()).unwrap();
+        }
+        // the keys are spread across the shards
+        assert!(kv.shards().all(|shard| shard.len() < 20));
+        let keys: Vec<_> = kv
+            .scan("default", "key05", Some("key10"))
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec!["key05", "key06", "key07", "key08", "key09"]);
+
+        kv.write("a").sadd("a", "x").unwrap();
+        kv.write("a").sadd("a", "y").unwrap();
+        kv.write("b").sadd("b", "y").unwrap();
+        assert_eq!(kv.sopstore(SetOp::Inter, "c", &["a", "b"]), Ok(1));
+        assert_eq!(kv.read("c").smembers("c"), Ok(vec!["y".to_string()]));
+
+        kv.create_namespace("users", KeyspaceOptions::default())
+            .unwrap();
+        assert!(kv
+            .create_namespace("users", KeyspaceOptions::default())
+            .is_err());
+        assert_eq!(kv.namespaces(), vec!["default", "users"]);
+        kv.drop_namespace("users").unwrap();
+        assert_eq!(kv.namespaces(), vec!["default"]);
+    }
+
+    #[test]
+    fn test_split_options() {
+        let options = KeyspaceOptions {
+            max_keys: Some(10),
+            max_memory: Some(1000),
+            ..Default::default()
+        };
+        let split = split_options(&options, 4).unwrap();
+        assert_eq!(split.max_keys, Some(2));
+        assert_eq!(
+            (split.max_memory, split.max_value_size),
+            (Some(250), Some(250))
+        );
+        assert!(split_options(&options, 11).is_err());
+        let options = KeyspaceOptions {
+            max_value_size: Some(300),
+            ..options
+        };
+        assert!(split_options(&options, 4).is_err());
+        assert!(split_options(&options, 2).is_ok());
+    }
+
+    #[test]
+    fn test_scan_batches() {
+        let kv = ShardedKvenna::new(2);
+        for i in 0..300 {
+            let key = format!("key{:03}", i);
+            kv.write(&key).put_string(&key, "v").unwrap();
+        }
+        // no shard is locked while an entry is handed out, so it can be written to
+        let mut keys = vec![];
+        kv.scan_each("default", "key", None, |key, _| {
+            let next = format!("key{:03}", (keys.len() + 100) % 300);
+            kv.write(&next).put_string(&next, "w").unwrap();
+            keys.push(key);
+            true
+        })
+        .unwrap();
+        let expected: Vec<_> = (0..300).map(|i| format!("key{:03}", i)).collect();
+        assert_eq!(keys, expected);
+    }
+}
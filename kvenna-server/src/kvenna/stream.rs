@@ -0,0 +1,393 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    errors::{KvennaError, Result},
+    keyspace::Keyspace,
+    memory::element_size,
+    value::ValueKind,
+    watch::EventKind,
+};
+
+// streams and their consumer groups only live in memory, whatever the storage engine of
+// the namespace, and are lost when the server stops
+
+// StreamId is `<milliseconds>-<sequence>`, ids only ever grow within a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+// parse `<ms>-<seq>`, or `<ms>` meaning sequence 0
+impl FromStr for StreamId {
+    type Err = KvennaError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (ms, seq) = s.split_once('-').unwrap_or((s, "0"));
+        match (ms.parse(), seq.parse()) {
+            (Ok(ms), Ok(seq)) => Ok(StreamId { ms, seq }),
+            _ => Err(KvennaError::InvalidStreamId(s.to_string())),
+        }
+    }
+}
+
+pub type Fields = Vec<(String, Vec<u8>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    pub fields: Fields,
+}
+
+// PendingEntry is an entry delivered to a consumer of a group but not acknowledged yet
+#[derive(Debug, Clone)]
+pub struct PendingEntry {
+    pub id: StreamId,
+    pub consumer: String,
+    pub delivered_at: Instant,
+    pub deliveries: u32,
+}
+
+#[derive(Default)]
+struct ConsumerGroup {
+    last_delivered: StreamId,
+    pending: BTreeMap<StreamId, PendingEntry>,
+}
+
+#[derive(Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    groups: HashMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    fn next_id(&self) -> StreamId {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        if now > self.last_id.ms {
+            StreamId::new(now, 0)
+        } else {
+            // the clock went backwards or several entries are added in the same millisecond
+            StreamId::new(self.last_id.ms, self.last_id.seq + 1)
+        }
+    }
+
+    fn range(&self, start: StreamId, end: StreamId, count: Option<usize>) -> Vec<StreamEntry> {
+        if start > end {
+            return vec![];
+        }
+        self.entries
+            .range(start..=end)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| StreamEntry {
+                id: *id,
+                fields: fields.clone(),
+            })
+            .collect()
+    }
+
+    // return the entries whose id is greater than after
+    fn after(&self, after: StreamId, count: Option<usize>) -> Vec<StreamEntry> {
+        if after == StreamId::MAX {
+            return vec![];
+        }
+        let start = match after.seq {
+            u64::MAX => StreamId::new(after.ms + 1, 0),
+            seq => StreamId::new(after.ms, seq + 1),
+        };
+        self.range(start, StreamId::MAX, count)
+    }
+
+    fn group_mut(&mut self, group: &str) -> Result<&mut ConsumerGroup> {
+        self.groups
+            .get_mut(group)
+            .ok_or_else(|| KvennaError::GroupNotFound(group.to_string()))
+    }
+}
+
+impl Keyspace {
+    fn stream(&self, key: &str) -> Result<Option<&Stream>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.check_kind(key, ValueKind::Stream)?;
+        self.touch(key);
+        Ok(self.streams.get(key))
+    }
+
+    fn stream_mut(&mut self, key: &str) -> Result<&mut Stream> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Stream)?;
+        self.touch(key);
+        self.streams
+            .get_mut(key)
+            .ok_or_else(|| KvennaError::KeyNotFound(key.to_string()))
+    }
+
+    // append an entry to the stream, creating it if it does not exist,
+    // and return the generated id
+    pub fn xadd(&mut self, key: &str, fields: Fields) -> Result<StreamId> {
+        let len = fields
+            .iter()
+            .map(|(field, value)| field.len() + value.len())
+            .sum();
+        self.check_size(key, len)?;
+        self.check_persisted(ValueKind::Stream)?;
+        let size = fields
+            .iter()
+            .map(|(field, value)| element_size(field.len() + value.len()))
+            .sum::<isize>()
+            + element_size(16);
+        self.make_room(key, size)?;
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::Stream)?;
+        if !self.streams.contains_key(key) {
+            self.reserve(key, None)?;
+        }
+        let stream = self.streams.entry(key.to_string()).or_default();
+        let id = stream.next_id();
+        stream.entries.insert(id, fields);
+        stream.last_id = id;
+        self.grow(key, size);
+        self.notify(key, EventKind::Put);
+        Ok(id)
+    }
+
+    pub fn xlen(&self, key: &str) -> Result<usize> {
+        Ok(self.stream(key)?.map_or(0, |stream| stream.entries.len()))
+    }
+
+    // return the entries whose id is between start and end, both inclusive
+    pub fn xrange(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>> {
+        Ok(self
+            .stream(key)?
+            .map(|stream| stream.range(start, end, count))
+            .unwrap_or_default())
+    }
+
+    // return the entries added after the given id
+    pub fn xread(
+        &self,
+        key: &str,
+        after: StreamId,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>> {
+        Ok(self
+            .stream(key)?
+            .map(|stream| stream.after(after, count))
+            .unwrap_or_default())
+    }
+
+    // create a consumer group which will deliver the entries added after start,
+    // None starts from the last entry of the stream
+    pub fn xgroup_create(&mut self, key: &str, group: &str, start: Option<StreamId>) -> Result<()> {
+        let stream = self.stream_mut(key)?;
+        if stream.groups.contains_key(group) {
+            return Err(KvennaError::GroupExists(group.to_string()));
+        }
+        let group_state = ConsumerGroup {
+            last_delivered: start.unwrap_or(stream.last_id),
+            pending: BTreeMap::new(),
+        };
+        stream.groups.insert(group.to_string(), group_state);
+        Ok(())
+    }
+
+    pub fn xgroup_destroy(&mut self, key: &str, group: &str) -> Result<bool> {
+        Ok(self.stream_mut(key)?.groups.remove(group).is_some())
+    }
+
+    // deliver the entries never delivered to the group to the consumer, they stay pending
+    // until they are acknowledged
+    pub fn xreadgroup(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>> {
+        let stream = self.stream_mut(key)?;
+        let last_delivered = stream.group_mut(group)?.last_delivered;
+        let entries = stream.after(last_delivered, count);
+        let group = stream.group_mut(group)?;
+        let now = Instant::now();
+        for entry in entries.iter() {
+            group.last_delivered = entry.id;
+            let pending = group.pending.entry(entry.id).or_insert(PendingEntry {
+                id: entry.id,
+                consumer: consumer.to_string(),
+                delivered_at: now,
+                deliveries: 0,
+            });
+            pending.deliveries += 1;
+        }
+        Ok(entries)
+    }
+
+    // hand the pending entries of the group which were delivered at least min_idle ago
+    // over to the consumer and deliver them again, so that the entries of a consumer
+    // which went away are not left pending forever
+    pub fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: Duration,
+        count: Option<usize>,
+    ) -> Result<Vec<StreamEntry>> {
+        let stream = self.stream_mut(key)?;
+        let group = stream
+            .groups
+            .get_mut(group)
+            .ok_or_else(|| KvennaError::GroupNotFound(group.to_string()))?;
+        let now = Instant::now();
+        let mut claimed = vec![];
+        for pending in group.pending.values_mut() {
+            if claimed.len() == count.unwrap_or(usize::MAX) {
+                break;
+            }
+            if now.duration_since(pending.delivered_at) < min_idle {
+                continue;
+            }
+            // entries are never removed from a stream, so a pending one is always found
+            if let Some(fields) = stream.entries.get(&pending.id) {
+                pending.consumer = consumer.to_string();
+                pending.delivered_at = now;
+                pending.deliveries += 1;
+                claimed.push(StreamEntry {
+                    id: pending.id,
+                    fields: fields.clone(),
+                });
+            }
+        }
+        Ok(claimed)
+    }
+
+    // acknowledge the entries, return the number of entries which were pending
+    pub fn xack(&mut self, key: &str, group: &str, ids: &[StreamId]) -> Result<usize> {
+        let group = self.stream_mut(key)?.group_mut(group)?;
+        Ok(ids
+            .iter()
+            .filter(|id| group.pending.remove(id).is_some())
+            .count())
+    }
+
+    // return the pending entries of the group in id order
+    pub fn xpending(&self, key: &str, group: &str) -> Result<Vec<PendingEntry>> {
+        let stream = self
+            .stream(key)?
+            .ok_or_else(|| KvennaError::KeyNotFound(key.to_string()))?;
+        let group = stream
+            .groups
+            .get(group)
+            .ok_or_else(|| KvennaError::GroupNotFound(group.to_string()))?;
+        Ok(group.pending.values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(value: &str) -> Fields {
+        vec![("event".to_string(), value.as_bytes().to_vec())]
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut ks = Keyspace::default();
+        let first = ks.xadd("events", fields("a")).unwrap();
+        let second = ks.xadd("events", fields("b")).unwrap();
+        let third = ks.xadd("events", fields("c")).unwrap();
+        assert!(first < second && second < third);
+        assert_eq!(ks.xlen("events"), Ok(3));
+
+        let ids = |entries: Vec<StreamEntry>| -> Vec<StreamId> {
+            entries.into_iter().map(|entry| entry.id).collect()
+        };
+        assert_eq!(
+            ids(ks
+                .xrange("events", StreamId::MIN, StreamId::MAX, Some(2))
+                .unwrap()),
+            vec![first, second]
+        );
+        assert_eq!(ids(ks.xread("events", second, None).unwrap()), vec![third]);
+        assert_eq!(first.to_string().parse(), Ok(first));
+    }
+
+    #[test]
+    fn test_consumer_group() {
+        let mut ks = Keyspace::default();
+        let first = ks.xadd("events", fields("a")).unwrap();
+        ks.xgroup_create("events", "workers", Some(StreamId::MIN))
+            .unwrap();
+        assert_eq!(
+            ks.xgroup_create("events", "workers", None),
+            Err(KvennaError::GroupExists("workers".to_string()))
+        );
+        let second = ks.xadd("events", fields("b")).unwrap();
+
+        let delivered = ks.xreadgroup("events", "workers", "w1", Some(1)).unwrap();
+        assert_eq!(delivered[0].id, first);
+        let delivered = ks.xreadgroup("events", "workers", "w2", None).unwrap();
+        assert_eq!(delivered[0].id, second);
+        assert!(ks
+            .xreadgroup("events", "workers", "w1", None)
+            .unwrap()
+            .is_empty());
+
+        let pending = ks.xpending("events", "workers").unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[1].consumer, "w2");
+        assert_eq!(ks.xack("events", "workers", &[first, first]), Ok(1));
+        assert_eq!(ks.xpending("events", "workers").unwrap()[0].id, second);
+
+        let claimed = ks
+            .xclaim("events", "workers", "w3", Duration::from_secs(60), None)
+            .unwrap();
+        assert!(claimed.is_empty());
+        let claimed = ks
+            .xclaim("events", "workers", "w3", Duration::ZERO, None)
+            .unwrap();
+        assert_eq!(claimed[0].id, second);
+        let pending = ks.xpending("events", "workers").unwrap();
+        assert_eq!(
+            (pending[0].consumer.as_str(), pending[0].deliveries),
+            ("w3", 2)
+        );
+    }
+}
@@ -0,0 +1,10 @@
+// ValueKind is the kind of value held by a key
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ValueKind {
+    String,
+    List,
+    Hash,
+    SortedSet,
+    Set,
+    Stream,
+}
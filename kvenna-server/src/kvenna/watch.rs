@@ -0,0 +1,236 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// number of events kept for watchers resuming from an older revision
+const HISTORY_SIZE: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum EventKind {
+    Put,
+    Delete,
+    Expire,
+    Evict,
+}
+
+impl From<EventKind> for &str {
+    fn from(value: EventKind) -> Self {
+        match value {
+            EventKind::Put => "put",
+            EventKind::Delete => "delete",
+            EventKind::Expire => "expire",
+            EventKind::Evict => "evict",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub revision: u64,
+    pub kind: EventKind,
+    pub namespace: String,
+    pub key: String,
+}
+
+// Compacted means the events after the requested revision are no longer kept
+#[derive(Debug, PartialEq, Eq)]
+pub struct Compacted {
+    pub oldest: u64,
+}
+
+// the events published by one shard, with the revision of the last one dropped
+#[derive(Default)]
+struct Stripe {
+    history: VecDeque<Event>,
+    dropped: u64,
+}
+
+// ChangeFeed numbers every change of the keyspaces with an increasing revision and
+// wakes up the watchers waiting for it. Every shard publishes to its own stripe, so
+// that writes to different shards don't contend, and the stripes share the revision
+// counter only.
+pub struct ChangeFeed {
+    stripes: Vec<Mutex<Stripe>>,
+    revision: AtomicU64,
+    // the watchers wait on the condvar, the publishers only take its lock to wake them
+    waiters: AtomicUsize,
+    wakeup: Mutex<()>,
+    cond: Condvar,
+}
+
+impl ChangeFeed {
+    pub fn new() -> Self {
+        Self::with_stripes(1)
+    }
+
+    pub fn with_stripes(n: usize) -> Self {
+        Self {
+            stripes: (0..n.max(1)).map(|_| Mutex::default()).collect(),
+            revision: AtomicU64::new(0),
+            waiters: AtomicUsize::new(0),
+            wakeup: Mutex::new(()),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn publish(&self, stripe: usize, namespace: &str, key: &str, kind: EventKind) {
+        let mut stripe = self.stripes[stripe % self.stripes.len()].lock().unwrap();
+        // the revision is taken under the stripe lock, so a watcher locking the stripe
+        // after reading the counter finds every event numbered up to it
+        let revision = self.revision.fetch_add(1, Ordering::SeqCst) + 1;
+        if stripe.history.len() == HISTORY_SIZE {
+            stripe.dropped = stripe.history.pop_front().unwrap().revision;
+        }
+        stripe.history.push_back(Event {
+            revision,
+            kind,
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+        });
+        drop(stripe);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _wakeup = self.wakeup.lock().unwrap();
+            self.cond.notify_all();
+        }
+    }
+
+    // the revision of the latest change
+    pub fn revision(&self) -> u64 {
+        self.revision.load(Ordering::SeqCst)
+    }
+
+    // whether the key changed after revision since, or may have as its events are
+    // compacted
+    pub fn changed_since(&self, since: u64, namespace: &str, key: &str) -> bool {
+        let filter = |event: &Event| event.namespace == namespace && event.key == key;
+        self.collect(since, self.revision(), &filter)
+            .map_or(true, |events| !events.is_empty())
+    }
+
+    // the events numbered in (since, until] accepted by the filter, in order
+    fn collect<F>(&self, since: u64, until: u64, filter: &F) -> Result<Vec<Event>, Compacted>
+    where
+        F: Fn(&Event) -> bool,
+    {
+        let mut events = vec![];
+        let mut dropped = 0;
+        for stripe in self.stripes.iter() {
+            let stripe = stripe.lock().unwrap();
+            dropped = dropped.max(stripe.dropped);
+            events.extend(
+                stripe
+                    .history
+                    .iter()
+                    .filter(|event| event.revision > since && event.revision <= until)
+                    .filter(|event| filter(event))
+                    .cloned(),
+            );
+        }
+        if since < dropped {
+            return Err(Compacted {
+                oldest: dropped + 1,
+            });
+        }
+        events.sort_by_key(|event| event.revision);
+        Ok(events)
+    }
+
+    // return the events after revision `since` accepted by the filter, waiting up to timeout
+    // for one to happen. The result is empty if the timeout elapsed.
+    pub fn wait<F>(&self, since: u64, filter: F, timeout: Duration) -> Result<Vec<Event>, Compacted>
+    where
+        F: Fn(&Event) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        let mut wakeup = self.wakeup.lock().unwrap();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut since = since;
+        let result = loop {
+            let until = self.revision();
+            match self.collect(since, until, &filter) {
+                Ok(events) if events.is_empty() => {}
+                result => break result,
+            }
+            // skip the events rejected by the filter on the next round
+            since = since.max(until);
+            let now = Instant::now();
+            if now >= deadline {
+                break Ok(vec![]);
+            }
+            wakeup = self.cond.wait_timeout(wakeup, deadline - now).unwrap().0;
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_wait() {
+        let feed = Arc::new(ChangeFeed::new());
+        feed.publish(0, "default", "other", EventKind::Put);
+        let publisher = feed.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            publisher.publish(0, "default", "config/a", EventKind::Delete);
+        });
+        let events = feed
+            .wait(0, |e| e.key.starts_with("config/"), Duration::from_secs(5))
+            .unwrap();
+        handle.join().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].revision, 2);
+        assert_eq!(events[0].kind, EventKind::Delete);
+
+        let events = feed.wait(2, |_| true, Duration::from_millis(10)).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_stripes() {
+        let feed = Arc::new(ChangeFeed::with_stripes(4));
+        let publishers: Vec<_> = (0..4)
+            .map(|stripe| {
+                let feed = feed.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        feed.publish(stripe, "default", &i.to_string(), EventKind::Put);
+                    }
+                })
+            })
+            .collect();
+        // the events of all the stripes come in order, none is skipped
+        let mut since = 0;
+        while since < 400 {
+            let events = feed.wait(since, |_| true, Duration::from_secs(5)).unwrap();
+            for event in events {
+                assert_eq!(event.revision, since + 1);
+                since = event.revision;
+            }
+        }
+        for publisher in publishers {
+            publisher.join().unwrap();
+        }
+
+        for _ in 0..HISTORY_SIZE {
+            feed.publish(0, "default", "key", EventKind::Put);
+        }
+        assert!(feed.wait(0, |_| true, Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use crate::skiplist::SkipList;
+
+use super::{
+    errors::{KvennaError, Result},
+    keyspace::Keyspace,
+    list::normalize_range,
+    memory::element_size,
+    value::ValueKind,
+};
+
+// a member is held by both the score map and the skiplist, prefixed by its score there
+fn member_size(member: &str) -> isize {
+    element_size(2 * member.len() + 24)
+}
+
+// map the score to a fixed width hex string whose lexical order is the numerical order
+fn encode_score(score: f64) -> String {
+    let bits = score.to_bits();
+    let bits = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    };
+    format!("{:016x}", bits)
+}
+
+fn decode_score(encoded: &str) -> f64 {
+    let bits = u64::from_str_radix(&encoded[..16], 16).unwrap();
+    let bits = if bits >> 63 == 1 {
+        bits & !(1 << 63)
+    } else {
+        !bits
+    };
+    f64::from_bits(bits)
+}
+
+// SortedSet orders its members by score, then by member. The skiplist is keyed by the
+// encoded score followed by the member, and the member index maps members to their scores.
+pub struct SortedSet {
+    skiplist: SkipList,
+    scores: HashMap<String, f64>,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self {
+            skiplist: SkipList::new(),
+            scores: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    // insert the member or update its score, return true if the member is new
+    pub fn insert(&mut self, member: &str, score: f64) -> bool {
+        let old = self.scores.insert(member.to_string(), score);
+        if let Some(old) = old {
+            self.skiplist.del(&(encode_score(old) + member));
+        }
+        self.skiplist.put(&(encode_score(score) + member), &[]);
+        old.is_none()
+    }
+
+    pub fn remove(&mut self, member: &str) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.skiplist.del(&(encode_score(score) + member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // iterate over the members and their scores in order
+    pub fn iter(&self) -> impl Iterator<Item = (String, f64)> + '_ {
+        self.iter_from(f64::NEG_INFINITY)
+    }
+
+    // iterate in order over the members whose score is not less than min
+    pub fn iter_from(&self, min: f64) -> impl Iterator<Item = (String, f64)> + '_ {
+        self.skiplist
+            .iter_from(&encode_score(min))
+            .map(|(key, _)| (key[16..].to_string(), decode_score(&key)))
+    }
+
+    // return the 0-based rank of the member, ordered by ascending score
+    pub fn rank(&self, member: &str) -> Option<usize> {
+        let score = self.score(member)?;
+        self.skiplist.rank(&(encode_score(score) + member))
+    }
+
+    // iterate in order over the members from the 0-based rank
+    pub fn iter_at(&self, rank: usize) -> impl Iterator<Item = (String, f64)> + '_ {
+        self.skiplist
+            .iter_at(rank)
+            .map(|(key, _)| (key[16..].to_string(), decode_score(&key)))
+    }
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keyspace {
+    fn zset(&self, key: &str) -> Result<Option<&SortedSet>> {
+        if self.is_expired(key) {
+            return Ok(None);
+        }
+        self.check_kind(key, ValueKind::SortedSet)?;
+        self.touch(key);
+        Ok(self.zsets.get(key))
+    }
+
+    fn zset_mut(&mut self, key: &str) -> Result<Option<&mut SortedSet>> {
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::SortedSet)?;
+        self.touch(key);
+        Ok(self.zsets.get_mut(key))
+    }
+
+    // add the member with the score to the sorted set, or update its score if it is
+    // already a member, return true if the member is new
+    pub fn zadd(&mut self, key: &str, score: f64, member: &str) -> Result<bool> {
+        if score.is_nan() {
+            return Err(KvennaError::NotAFloat);
+        }
+        self.check_size(key, member.len())?;
+        self.check_persisted(ValueKind::SortedSet)?;
+        self.make_room(key, member_size(member))?;
+        self.purge_if_expired(key)?;
+        self.check_kind(key, ValueKind::SortedSet)?;
+        if !self.zsets.contains_key(key) {
+            self.reserve(key, None)?;
+        }
+        let zset = self.zsets.entry(key.to_string()).or_default();
+        let created = zset.insert(member, score);
+        if created {
+            self.grow(key, member_size(member));
+        }
+        self.notify_write(key);
+        Ok(created)
+    }
+
+    // remove the member, return false if it is not a member
+    pub fn zrem(&mut self, key: &str, member: &str) -> Result<bool> {
+        let Some(zset) = self.zset_mut(key)? else {
+            return Ok(false);
+        };
+        let removed = zset.remove(member);
+        let emptied = zset.is_empty();
+        if removed {
+            self.grow(key, -member_size(member));
+        }
+        // empty sorted sets are removed, just like in redis
+        if emptied {
+            self.remove_key(key)?;
+        }
+        if removed {
+            self.notify_write(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn zscore(&self, key: &str, member: &str) -> Result<Option<f64>> {
+        Ok(self.zset(key)?.and_then(|zset| zset.score(member)))
+    }
+
+    pub fn zrank(&self, key: &str, member: &str) -> Result<Option<usize>> {
+        Ok(self.zset(key)?.and_then(|zset| zset.rank(member)))
+    }
+
+    // return the members ranked between start and stop, both inclusive,
+    // negative ranks count from the highest score
+    pub fn zrange(&self, key: &str, start: isize, stop: isize) -> Result<Vec<(String, f64)>> {
+        let Some(zset) = self.zset(key)? else {
+            return Ok(vec![]);
+        };
+        match normalize_range(start, stop, zset.len()) {
+            Some((start, stop)) => Ok(zset.iter_at(start).take(stop - start + 1).collect()),
+            None => Ok(vec![]),
+        }
+    }
+
+    // return the members whose score is between min and max, both inclusive
+    pub fn zrangebyscore(&self, key: &str, min: f64, max: f64) -> Result<Vec<(String, f64)>> {
+        let Some(zset) = self.zset(key)? else {
+            return Ok(vec![]);
+        };
+        Ok(zset
+            .iter_from(min)
+            .take_while(|(_, score)| *score <= max)
+            .collect())
+    }
+
+    // increment the score of the member by delta, a new member starts from 0,
+    // and return the new score
+    pub fn zincrby(&mut self, key: &str, delta: f64, member: &str) -> Result<f64> {
+        let score = self.zscore(key, member)?.unwrap_or(0.0) + delta;
+        self.zadd(key, score, member)?;
+        Ok(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_encoding() {
+        let scores = [f64::NEG_INFINITY, -10.5, -1.0, -0.0, 0.0, 0.25, 3.0, 1e10];
+        for pair in scores.windows(2) {
+            assert!(encode_score(pair[0]) <= encode_score(pair[1]));
+        }
+        for score in scores {
+            assert_eq!(decode_score(&encode_score(score)), score);
+        }
+    }
+
+    #[test]
+    fn test_zset() {
+        let mut ks = Keyspace::default();
+        assert_eq!(ks.zadd("board", 30.0, "carol"), Ok(true));
+        assert_eq!(ks.zadd("board", 10.0, "alice"), Ok(true));
+        assert_eq!(ks.zadd("board", -5.0, "bob"), Ok(true));
+        assert_eq!(ks.zadd("board", 20.0, "bob"), Ok(false));
+        assert_eq!(ks.zincrby("board", 15.0, "alice"), Ok(25.0));
+
+        let members = |entries: Vec<(String, f64)>| -> Vec<String> {
+            entries.into_iter().map(|(member, _)| member).collect()
+        };
+        assert_eq!(
+            members(ks.zrange("board", 0, -1).unwrap()),
+            vec!["bob", "alice", "carol"]
+        );
+        assert_eq!(members(ks.zrange("board", -1, -1).unwrap()), vec!["carol"]);
+        assert_eq!(
+            members(ks.zrangebyscore("board", 20.0, 25.0).unwrap()),
+            vec!["bob", "alice"]
+        );
+        assert_eq!(ks.zrank("board", "carol"), Ok(Some(2)));
+        assert_eq!(ks.zscore("board", "bob"), Ok(Some(20.0)));
+
+        assert_eq!(ks.zrem("board", "bob"), Ok(true));
+        assert_eq!(ks.zrank("board", "bob"), Ok(None));
+        assert_eq!(ks.zadd("board", f64::NAN, "x"), Err(KvennaError::NotAFloat));
+    }
+
+    #[test]
+    fn test_ranks() {
+        let mut zset = SortedSet::new();
+        for i in 0..500 {
+            zset.insert(&format!("m{}", i), (i * 7 % 500) as f64);
+        }
+        for i in (0..500).step_by(3) {
+            zset.remove(&format!("m{}", i));
+        }
+        let members: Vec<_> = zset.iter().collect();
+        for (rank, (member, _)) in members.iter().enumerate() {
+            assert_eq!(zset.rank(member), Some(rank));
+            assert_eq!(zset.iter_at(rank).next().as_ref(), Some(&members[rank]));
+        }
+        assert_eq!(zset.iter_at(members.len()).next(), None);
+    }
+}
@@ -0,0 +1,13 @@
+// Kvenna is an in-memory key-value store served over a small HTTP framework. The store,
+// the framework and the skiplist can be embedded on their own, the `kevanna` binary
+// puts them together.
+pub mod error;
+pub mod handlers;
+pub mod kvenna;
+pub mod server;
+pub mod skiplist;
+
+pub use error::{Error, Result};
+pub use kvenna::{Keyspace, KeyspaceOptions, Kvenna, KvennaError, ShardedKvenna};
+pub use server::{Context, Router, Server, ServerConfig};
+pub use skiplist::SkipList;
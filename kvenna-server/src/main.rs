@@ -1,19 +1,9 @@
-mod kvenna;
-mod server;
-mod skiplist;
-
-use std::{
-    io,
-    sync::{Arc, Mutex},
-};
+use std::{io, sync::Arc, thread, time::Duration};
 
 use argparse::{ArgumentParser, Store, StoreTrue};
-use skiplist::helper;
-
-use crate::{
-    kvenna::Kvenna,
-    server::{request::Url, status, Server},
-    skiplist::SkipList,
+use kevanna::{
+    handlers, kvenna::engine::Backend, skiplist::helper, KeyspaceOptions, Server, ServerConfig,
+    ShardedKvenna, SkipList,
 };
 
 fn print_value(value: Option<Vec<u8>>) {
@@ -61,6 +51,16 @@ struct Options {
     pub interactive: bool,
     pub host: String,
     pub port: u16,
+    pub max_memory: usize,
+    pub eviction: String,
+    pub backend: String,
+    pub fsync: String,
+    pub max_key_len: usize,
+    pub max_value_size: usize,
+    pub shards: usize,
+    pub cors_origin: String,
+    pub max_connections: usize,
+    pub header_timeout: u64,
 }
 
 fn main() {
@@ -68,6 +68,16 @@ fn main() {
         interactive: false,
         host: "127.0.0.1".to_string(),
         port: 5000,
+        max_memory: 0,
+        eviction: "noeviction".to_string(),
+        backend: "skiplist".to_string(),
+        fsync: "everysec".to_string(),
+        max_key_len: 0,
+        max_value_size: 0,
+        shards: thread::available_parallelism().map_or(1, |n| n.get()),
+        cors_origin: String::new(),
+        max_connections: 0,
+        header_timeout: 0,
     };
     {
         let mut ap = ArgumentParser::new();
@@ -81,6 +91,59 @@ fn main() {
             .add_option(&["-h", "--host"], Store, "Server host");
         ap.refer(&mut opt.port)
             .add_option(&["-p", "--port"], Store, "Server port");
+        ap.refer(&mut opt.max_memory).add_option(
+            &["--maxmemory"],
+            Store,
+            "Memory limit of the default namespace in bytes, 0 for no limit. It is split \
+             between the shards, each evicting on its own, and bounds the value size to the \
+             share of a shard",
+        );
+        ap.refer(&mut opt.eviction).add_option(
+            &["--maxmemory-policy"],
+            Store,
+            "Eviction policy: noeviction, allkeys-lru, allkeys-lfu, volatile-ttl or random",
+        );
+        ap.refer(&mut opt.backend).add_option(
+            &["--backend"],
+            Store,
+            "Storage engine of the string values: skiplist, btree or disk:<dir>, which only persists string values and refuses the other kinds, ttls and namespace options",
+        );
+        ap.refer(&mut opt.fsync).add_option(
+            &["--fsync"],
+            Store,
+            "When the disk backend syncs its writes: always, everysec or no",
+        );
+        ap.refer(&mut opt.max_key_len).add_option(
+            &["--max-key-len"],
+            Store,
+            "Maximum key length of the default namespace in bytes, 0 for no limit",
+        );
+        ap.refer(&mut opt.max_value_size).add_option(
+            &["--max-value-size"],
+            Store,
+            "Maximum value size in bytes, also the maximum request body, 0 for the defaults",
+        );
+        ap.refer(&mut opt.shards).add_option(
+            &["--shards"],
+            Store,
+            "Number of shards the keys are spread over, defaults to the number of cores. A disk \
+             directory has to be reopened with the number it was created with",
+        );
+        ap.refer(&mut opt.cors_origin).add_option(
+            &["--cors-origin"],
+            Store,
+            "Comma-separated origins allowed to make cross-origin requests, * for any",
+        );
+        ap.refer(&mut opt.max_connections).add_option(
+            &["--max-connections"],
+            Store,
+            "Connections served at once, the others get 503, 0 or more than the workers for one per worker",
+        );
+        ap.refer(&mut opt.header_timeout).add_option(
+            &["--header-timeout"],
+            Store,
+            "Seconds a request has to send its headers in, 0 for the default",
+        );
         ap.parse_args_or_exit();
     }
 
@@ -91,34 +154,53 @@ fn main() {
     }
 
     let addr = format!("{}:{}", opt.host, opt.port);
+    let Ok(eviction) = opt.eviction.parse() else {
+        eprintln!("unknown eviction policy {}", opt.eviction);
+        std::process::exit(2);
+    };
+    let options = KeyspaceOptions {
+        max_memory: (opt.max_memory > 0).then_some(opt.max_memory),
+        eviction,
+        max_key_len: (opt.max_key_len > 0).then_some(opt.max_key_len),
+        max_value_size: (opt.max_value_size > 0).then_some(opt.max_value_size),
+        ..Default::default()
+    };
+    let kvenna = opt
+        .backend
+        .parse::<Backend>()
+        .and_then(|backend| Ok(backend.with_sync(opt.fsync.parse()?)))
+        .and_then(|backend| ShardedKvenna::open(backend, options, opt.shards));
+    let kvenna = match kvenna {
+        Ok(kvenna) => kvenna,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let kv_store = Arc::new(kvenna);
+    // expired keys are removed lazily on access, sweep the rest in the background
+    let sweeper = kv_store.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        if let Err(err) = sweeper.purge_expired() {
+            eprintln!("[SWEEP] {}", err);
+        }
+    });
+    let mut config = ServerConfig::default();
+    if opt.max_value_size > 0 {
+        config.max_body_size = opt.max_value_size;
+    }
+    if opt.max_connections > 0 {
+        config.max_connections = opt.max_connections;
+    }
+    if opt.header_timeout > 0 {
+        config.header_timeout = Duration::from_secs(opt.header_timeout);
+    }
+    let mut server = Server::with_config(config);
+    for origin in opt.cors_origin.split(',').filter(|o| !o.is_empty()) {
+        server.allow_origin(origin.trim());
+    }
+    handlers::bind(&mut server, kv_store);
     println!("Server is running on {}", addr);
-    let kv_store = Arc::new(Mutex::new(Kvenna::new()));
-    let mut server = Server::new();
-    let cloned = kv_store.clone();
-    server
-        .bind_get(&Url::new("/:key"), move |c| {
-            let url = &c.req.url;
-            let key = url.get_param("key").unwrap();
-            let val = &cloned.lock().unwrap().get_string(key);
-            match val {
-                // if val does exist, then return it as string
-                Some(val) => {
-                    println!("[GET] key = {}, got value = {:?}", key, val);
-                    c.write_text(&val)?;
-                }
-                // otherwise, we should set the status code as 404 NOT FOUND
-                None => c.status(status::NOT_FOUND),
-            }
-            Ok(())
-        })
-        .bind_put(&Url::new("/:key/:value"), move |c| {
-            let url = &c.req.url;
-            let key = url.get_param("key").unwrap();
-            let val = url.get_param("value").unwrap();
-            println!("[PUT] {} -> {}", key, val);
-            kv_store.lock().unwrap().put_string(key, val);
-            c.write_text("ok")?;
-            Ok(())
-        });
     server.run(&addr);
 }
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+// ServerConfig holds the limits protecting the server from oversized requests and
+// idle connections
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    // number of threads handling the connections
+    pub workers: usize,
+    // maximum length of the request target in bytes, longer ones get 414
+    pub max_uri_len: usize,
+    // maximum length of the request body in bytes, larger ones get 413
+    pub max_body_size: usize,
+    // maximum size of the request headers in bytes, and maximum number of fields, larger
+    // ones get 431
+    pub max_header_size: usize,
+    pub max_headers: usize,
+    // a persistent connection is closed once it has waited this long for a request
+    pub idle_timeout: Duration,
+    // once a request starts, its request line and headers have to arrive within
+    // header_timeout and then its body within body_timeout, or it gets 408
+    pub header_timeout: Duration,
+    pub body_timeout: Duration,
+    // a connection is closed once a response, or an event of a stream, has not been
+    // read by the client within this long
+    pub write_timeout: Duration,
+    // a persistent connection is closed after serving this many requests
+    pub max_requests: usize,
+    // connections beyond this many at once get 503, at most one per worker so that none
+    // waits for a worker without being timed
+    pub max_connections: usize,
+    // watches and subscriptions hold their worker until the client goes away, those
+    // beyond this many at once get 503 so that workers are left for the other requests
+    pub max_held_workers: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            workers: 300,
+            max_uri_len: 8 * 1024,
+            max_body_size: 16 * 1024 * 1024,
+            max_header_size: 16 * 1024,
+            max_headers: 100,
+            idle_timeout: Duration::from_secs(5),
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            max_requests: 1000,
+            max_connections: 300,
+            max_held_workers: 150,
+        }
+    }
+}
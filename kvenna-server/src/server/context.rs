@@ -1,13 +1,41 @@
 #![allow(dead_code)]
-use std::{borrow::Borrow, io, net::TcpStream};
+use std::{
+    borrow::Borrow,
+    io::{self, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use super::{headers, response, HttpRequest, HttpResponse};
+use super::{
+    errors::ServerError,
+    headers,
+    response::{self, TimedWriter},
+    server::ConnectionSlot,
+    status::{self, StatusCode},
+    version::Version,
+    HttpRequest, HttpResponse, Method, ServerConfig,
+};
 
 pub struct Context<'a> {
     pub req: HttpRequest,
     pub res: HttpResponse,
     pub has_written: bool,
+    // whether the connection serves another request after this one
+    pub keep_alive: bool,
+    // a chunked body has been started and not ended yet
+    chunked: bool,
     pub stream: &'a mut TcpStream,
+    // a response has to be read by the client within write_timeout of its start
+    write_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    // the handlers holding their worker while they wait on events, and how many may
+    holding: Option<Arc<AtomicUsize>>,
+    max_holding: usize,
+    held: Option<ConnectionSlot>,
 }
 
 impl<'a> Context<'a> {
@@ -17,28 +45,174 @@ impl<'a> Context<'a> {
             res,
             stream,
             has_written: false,
+            keep_alive: false,
+            chunked: false,
+            write_timeout: None,
+            deadline: None,
+            holding: None,
+            max_holding: 0,
+            held: None,
         }
     }
 
-    pub fn status(&mut self, status: u32) {
+    // apply the write timeout and the limit on held workers of the server
+    pub(super) fn limit(&mut self, config: &ServerConfig, holding: Arc<AtomicUsize>) {
+        self.write_timeout = Some(config.write_timeout);
+        self.max_holding = config.max_held_workers;
+        self.holding = Some(holding);
+    }
+
+    // take one of the workers kept for handlers waiting on events, such as watches and
+    // subscriptions, for the rest of the request. Once they are all taken the request
+    // gets 503 and false is returned.
+    pub fn hold_worker(&mut self) -> io::Result<bool> {
+        let Some(holding) = &self.holding else {
+            return Ok(true);
+        };
+        if holding.fetch_add(1, Ordering::SeqCst) >= self.max_holding {
+            holding.fetch_sub(1, Ordering::SeqCst);
+            let err = ServerError::TooManyHeldWorkers(self.max_holding);
+            self.status(status::SERVICE_UNAVAILABLE);
+            self.res.add_header(headers::RETRY_AFTER, "1");
+            self.write_text(&err.to_string())?;
+            return Ok(false);
+        }
+        self.held = Some(ConnectionSlot(holding.clone()));
+        Ok(true)
+    }
+
+    // start the deadline of a response
+    fn start_response(&mut self) {
+        self.deadline = self.write_timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    fn writer(&mut self) -> TimedWriter<'_> {
+        TimedWriter::new(self.stream, self.deadline)
+    }
+
+    pub fn is_head(&self) -> bool {
+        self.req.method == Method::Head
+    }
+
+    pub fn status(&mut self, status: StatusCode) {
         self.res.status(status);
     }
 
     pub fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.write_bytes(text.as_bytes())
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
         self.res
             .add_header(headers::CONTENT_TYPE, headers::CONTENT_TEXT_HTML)
-            .add_header(headers::CONTENT_LENGTH, text.len().to_string().borrow())
-            .body(text.as_bytes().to_vec());
+            .add_header(headers::CONTENT_LENGTH, bytes.len().to_string().borrow());
+        // the answer to HEAD has the headers of the answer to GET, but no body
+        if !self.is_head() {
+            self.res.body(bytes.to_vec());
+        }
 
-        response::write_response(self.stream, &mut self.res)?;
+        self.start_response();
+        let mut writer = TimedWriter::new(self.stream, self.deadline);
+        response::write_response(&mut writer, &self.res)?;
         // mark as has written
         self.has_written = true;
         Ok(())
     }
 
+    // write the response head without a length, the body is then sent piece by piece
+    // with write_raw and ends when the connection is closed
+    pub fn start_stream(&mut self, content_type: &str) -> io::Result<()> {
+        self.keep_alive = false;
+        self.res
+            .add_header(headers::CONTENT_TYPE, content_type)
+            .add_header(headers::CONNECTION, "close");
+        self.start_response();
+        let mut writer = TimedWriter::new(self.stream, self.deadline);
+        response::write_response(&mut writer, &self.res)?;
+        self.has_written = true;
+        Ok(())
+    }
+
+    // write the response head and send the body piece by piece with write_chunk, it ends
+    // with end_chunks or once the handler returns. HTTP/1.0 clients do not know about
+    // chunks, they get the pieces as they are and the connection closed after them.
+    pub fn start_chunks(&mut self, content_type: &str) -> io::Result<()> {
+        if self.req.version == Version::V10 {
+            return self.start_stream(content_type);
+        }
+        self.res
+            .add_header(headers::CONTENT_TYPE, content_type)
+            .add_header(headers::TRANSFER_ENCODING, "chunked");
+        self.start_response();
+        let mut writer = TimedWriter::new(self.stream, self.deadline);
+        response::write_response(&mut writer, &self.res)?;
+        self.has_written = true;
+        self.chunked = true;
+        Ok(())
+    }
+
+    pub fn write_chunk(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // an empty chunk would end the body
+        if self.is_head() {
+            return Ok(());
+        } else if !self.chunked {
+            return self.write_raw(bytes);
+        } else if bytes.is_empty() {
+            return Ok(());
+        }
+        // the chunk goes out in one write, see write_response
+        let mut chunk = format!("{:x}\r\n", bytes.len()).into_bytes();
+        chunk.extend_from_slice(bytes);
+        chunk.extend_from_slice(b"\r\n");
+        self.write_raw(&chunk)
+    }
+
+    pub fn end_chunks(&mut self) -> io::Result<()> {
+        if !self.chunked {
+            return Ok(());
+        }
+        self.chunked = false;
+        if self.is_head() {
+            return Ok(());
+        }
+        self.write_raw(b"0\r\n\r\n")
+    }
+
+    // a chunked body cut short by a failed handler is left without its last chunk, so
+    // that the client does not take it as complete, and the connection is closed
+    pub(super) fn abort_chunks(&mut self) {
+        if self.chunked {
+            self.chunked = false;
+            self.keep_alive = false;
+        }
+    }
+
+    // a stream answering HEAD fails on its first write, so that the handler stops
+    // producing a body which is not sent. The events sent by a held worker have no end,
+    // each one has its own deadline.
+    pub fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.is_head() {
+            return Err(io::Error::other("no body is sent in answer to HEAD"));
+        }
+        if self.held.is_some() {
+            self.start_response();
+        }
+        let mut writer = self.writer();
+        writer.write_all(bytes)?;
+        writer.flush()
+    }
+
     // write the basic response status
     pub(super) fn write_empty(&mut self) -> io::Result<()> {
-        response::write_response(self.stream, &mut self.res)?;
+        // the client needs the length to find the next response on the connection, but
+        // the responses which never have a body must not tell one
+        let bodiless = matches!(self.res.status_code.as_u16(), 100..=199 | 204 | 304);
+        if !bodiless && self.res.headers.get(headers::CONTENT_LENGTH).is_none() {
+            self.res.add_header(headers::CONTENT_LENGTH, "0");
+        }
+        self.start_response();
+        let mut writer = TimedWriter::new(self.stream, self.deadline);
+        response::write_response(&mut writer, &self.res)?;
         Ok(())
     }
 }
@@ -4,12 +4,31 @@ use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, ServerError>;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum ServerError {
     #[error("io error")]
     IoError(#[from] io::Error),
     #[error("route url `{0}` is not configured")]
     RouteError(String),
+    #[error("route conflict: {0}")]
+    RouteConflict(String),
+    #[error("method `{0}` is not allowed on route url `{1}`")]
+    MethodNotAllowed(String, String),
     #[error("invalid http request")]
     PareRequestError,
+    #[error("connection closed")]
+    ConnectionClosed,
+    #[error("request body is larger than {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("request uri is longer than {0} bytes")]
+    UriTooLong(usize),
+    #[error("request headers are larger than {0} bytes or more than {1} fields")]
+    HeadersTooLarge(usize, usize),
+    #[error("request was not received in time")]
+    RequestTimeout,
+    #[error("too many connections, at most {0} are served at once")]
+    TooManyConnections(usize),
+    #[error("too many watches and subscriptions, at most {0} are served at once")]
+    TooManyHeldWorkers(usize),
 }
@@ -1,42 +1,119 @@
 #![allow(dead_code)]
 
-use std::{
-    borrow::{Borrow, BorrowMut},
-    collections::HashMap,
-    ops::{Deref, DerefMut},
-};
-
 pub const USER_AGENT: &str = "User-Agent";
 pub const CONTENT_LENGTH: &str = "Content-Length";
 pub const CONTENT_TYPE: &str = "Content-Type";
+pub const CONTENT_RANGE: &str = "Content-Range";
+pub const ACCEPT_RANGES: &str = "Accept-Ranges";
+pub const RANGE: &str = "Range";
+pub const CACHE_CONTROL: &str = "Cache-Control";
+pub const LAST_EVENT_ID: &str = "Last-Event-ID";
+pub const CONNECTION: &str = "Connection";
+pub const TRANSFER_ENCODING: &str = "Transfer-Encoding";
+pub const ALLOW: &str = "Allow";
+pub const RETRY_AFTER: &str = "Retry-After";
+pub const ORIGIN: &str = "Origin";
+pub const VARY: &str = "Vary";
+pub const ACCESS_CONTROL_ALLOW_ORIGIN: &str = "Access-Control-Allow-Origin";
+pub const ACCESS_CONTROL_ALLOW_METHODS: &str = "Access-Control-Allow-Methods";
+pub const ACCESS_CONTROL_ALLOW_HEADERS: &str = "Access-Control-Allow-Headers";
+pub const ACCESS_CONTROL_MAX_AGE: &str = "Access-Control-Max-Age";
+pub const ACCESS_CONTROL_REQUEST_METHOD: &str = "Access-Control-Request-Method";
+pub const ACCESS_CONTROL_REQUEST_HEADERS: &str = "Access-Control-Request-Headers";
 pub const CONTENT_TEXT_HTML: &str = "text/html; charset=utf-8";
+pub const CONTENT_EVENT_STREAM: &str = "text/event-stream";
+
+// Headers keeps the fields of a message in the order they were added, with the case of
+// their names, while the lookups ignore the case. A name may have several values, like
+// the Set-Cookie fields of a response or a header repeated by a client.
+#[derive(Debug, Clone, Default)]
+pub struct Headers(Vec<(String, String)>);
 
-#[derive(Debug)]
-pub struct Headers(HashMap<String, String>);
+// whether the name is a token, so that it cannot end the field or the head
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+// whether the value holds no line break, which would let it inject fields
+pub fn is_valid_value(value: &str) -> bool {
+    !value.bytes().any(|b| matches!(b, b'\r' | b'\n' | b'\0'))
+}
 
 impl Headers {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(vec![])
     }
 
-    pub fn get(&self, key: &str) -> Option<String> {
-        self.0.get(key).cloned()
+    // the first value of the name
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.get_all(name).first().map(|value| value.to_string())
     }
 
-    pub fn put(&mut self, key: &str, value: &str) {
-        self.0.insert(key.to_string(), value.to_string());
+    // every value of the name, in the order they were added
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+
+    // set the value of the name, replacing its values
+    pub fn put(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
     }
-}
 
-impl Deref for Headers {
-    type Target = HashMap<String, String>;
-    fn deref(&self) -> &Self::Target {
-        self.0.borrow()
+    // add a value to the name, keeping its other values
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.0.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 }
 
-impl DerefMut for Headers {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.borrow_mut()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headers() {
+        let mut headers = Headers::new();
+        headers.append("content-length", "5");
+        headers.append("Accept", "text/html");
+        headers.append("ACCEPT", "text/plain");
+        assert_eq!(headers.get(CONTENT_LENGTH), Some("5".to_string()));
+        assert_eq!(headers.get_all("accept"), vec!["text/html", "text/plain"]);
+        headers.put("Accept", "*/*");
+        assert_eq!(headers.get_all("accept"), vec!["*/*"]);
+        assert_eq!(headers.len(), 2);
+
+        assert!(is_valid_name("X-Custom_1"));
+        assert!(!is_valid_name("X-Bad\r\nSet-Cookie"));
+        assert!(!is_valid_name("Bad Name"));
+        assert!(!is_valid_value("a\r\nSet-Cookie: b"));
     }
 }
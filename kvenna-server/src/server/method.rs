@@ -1,9 +1,12 @@
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Method {
     Get,
+    Head,
     Post,
     Put,
+    Patch,
     Delete,
+    Options,
     Unsupported,
 }
 
@@ -11,21 +14,27 @@ impl From<&str> for Method {
     fn from(value: &str) -> Self {
         match value {
             "GET" => Method::Get,
+            "HEAD" => Method::Head,
             "POST" => Method::Post,
             "PUT" => Method::Put,
+            "PATCH" => Method::Patch,
             "DELETE" => Method::Delete,
+            "OPTIONS" => Method::Options,
             _ => Method::Unsupported,
         }
     }
 }
 
-impl Into<&str> for Method {
-    fn into(self) -> &'static str {
-        match self {
+impl From<Method> for &str {
+    fn from(value: Method) -> Self {
+        match value {
             Method::Get => "GET",
+            Method::Head => "HEAD",
             Method::Post => "POST",
             Method::Put => "PUT",
+            Method::Patch => "PATCH",
             Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
             Method::Unsupported => "UNSUPPORTED",
         }
     }
@@ -37,7 +46,10 @@ mod tests {
 
     #[test]
     fn test_method() {
-        let get: Method = "Get".into();
+        let get: Method = "GET".into();
         assert_eq!(get, Method::Get);
+        // the methods are case-sensitive
+        let get: Method = "Get".into();
+        assert_eq!(get, Method::Unsupported);
     }
 }
@@ -1,15 +1,19 @@
+pub mod config;
 pub mod context;
 pub mod errors;
 pub mod headers;
 pub mod method;
+pub mod range;
 pub mod request;
 pub mod response;
 pub mod router;
+#[allow(clippy::module_inception)]
 pub mod server;
 pub mod status;
 pub mod thread;
 pub mod version;
 
+pub use config::ServerConfig;
 pub use context::Context;
 pub use method::Method;
 pub use request::HttpRequest;
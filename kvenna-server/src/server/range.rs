@@ -0,0 +1,69 @@
+#![allow(dead_code)]
+
+// ByteRange is a single range from a `Range: bytes=...` request header
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ByteRange {
+    // bytes=start-end, end is inclusive
+    FromTo(usize, usize),
+    // bytes=start-
+    From(usize),
+    // bytes=-len, the last len bytes
+    Suffix(usize),
+}
+
+impl ByteRange {
+    // parse a range header, only a single range of unit `bytes` is supported;
+    // anything else yields None and the header should be ignored
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+        match (start.is_empty(), end.is_empty()) {
+            (false, false) => {
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                if start > end {
+                    return None;
+                }
+                Some(ByteRange::FromTo(start, end))
+            }
+            (false, true) => Some(ByteRange::From(start.parse().ok()?)),
+            (true, false) => Some(ByteRange::Suffix(end.parse().ok()?)),
+            (true, true) => None,
+        }
+    }
+
+    // resolve the range against the length of a representation, return the
+    // inclusive bounds, or None if the range is not satisfiable
+    pub fn resolve(&self, len: usize) -> Option<(usize, usize)> {
+        if len == 0 {
+            return None;
+        }
+        match *self {
+            ByteRange::FromTo(start, end) if start < len => Some((start, end.min(len - 1))),
+            ByteRange::From(start) if start < len => Some((start, len - 1)),
+            ByteRange::Suffix(n) if n > 0 => Some((len - n.min(len), len - 1)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(ByteRange::parse("bytes=0-4"), Some(ByteRange::FromTo(0, 4)));
+        assert_eq!(ByteRange::parse("bytes=5-"), Some(ByteRange::From(5)));
+        assert_eq!(ByteRange::parse("bytes=-3"), Some(ByteRange::Suffix(3)));
+        assert_eq!(ByteRange::parse("bytes=0-1,4-5"), None);
+        assert_eq!(ByteRange::parse("items=0-1"), None);
+
+        assert_eq!(ByteRange::FromTo(2, 100).resolve(10), Some((2, 9)));
+        assert_eq!(ByteRange::From(10).resolve(10), None);
+        assert_eq!(ByteRange::Suffix(20).resolve(10), Some((0, 9)));
+    }
+}
@@ -1,13 +1,14 @@
 #![allow(dead_code)]
 
 use std::{
-    borrow::BorrowMut,
     collections::HashMap,
-    io::{BufRead, BufReader, Read},
+    io::{self, BufRead, BufReader, Read},
     net::TcpStream,
+    time::{Duration, Instant},
 };
 
 use super::{
+    config::ServerConfig,
     errors::{self, ServerError},
     headers::{self, Headers},
     method::Method,
@@ -16,16 +17,71 @@ use super::{
 
 pub type ParamsMap = HashMap<String, String>;
 
-#[derive(Debug, Clone)]
+// Url is a request target, split into the path and the query. The path is kept encoded
+// so that an encoded slash does not separate segments, the router decodes each segment
+// after splitting it.
+#[derive(Debug, Clone, Default)]
 pub struct Url {
     raw: String,
+    path: String,
+    // the decoded query pairs in their order in the url
+    query: Vec<(String, String)>,
     params: Option<ParamsMap>,
 }
 
+// decode the %XX escapes, and the plus signs as spaces if plus is set. The malformed
+// escapes are kept as they are, and invalid utf-8 is replaced.
+pub fn percent_decode(s: &str, plus: bool) -> String {
+    String::from_utf8_lossy(&percent_decode_bytes(s.as_bytes(), plus)).into_owned()
+}
+
+// decode the %XX escapes like percent_decode, keeping the decoded bytes as they are
+pub fn percent_decode_bytes(bytes: &[u8], plus: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = || {
+            let digits = bytes.get(i + 1..i + 3)?;
+            // from_str_radix would accept a sign too
+            if !digits.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+        };
+        match bytes[i] {
+            b'%' => {
+                if let Some(byte) = hex() {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                decoded.push(b'%');
+            }
+            b'+' if plus => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    decoded
+}
+
 impl Url {
     pub fn new(raw: &str) -> Self {
+        let (path, query) = raw.split_once('?').unwrap_or((raw, ""));
+        // a fragment is not sent by clients, but is not part of the query either
+        let query = query.split('#').next().unwrap_or_default();
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(name, true), percent_decode(value, true))
+            })
+            .collect();
         Self {
             raw: raw.to_owned(),
+            path: path.to_owned(),
+            query,
             params: None,
         }
     }
@@ -34,6 +90,28 @@ impl Url {
         &self.raw
     }
 
+    // the path, still percent-encoded
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // the first value of the query parameter
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // every value of the query parameter, like `a` and `b` for `?tag=a&tag=b`
+    pub fn query_all(&self, name: &str) -> Vec<&str> {
+        self.query
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
     pub fn set_params(&mut self, params: ParamsMap) {
         self.params = Some(params);
     }
@@ -46,21 +124,14 @@ impl Url {
     }
 }
 
-impl Default for Url {
-    fn default() -> Self {
-        Self {
-            raw: String::new(),
-            params: None,
-        }
-    }
-}
-
 pub struct HttpRequest {
     pub method: Method,
     pub url: Url,
     pub version: Version,
     pub headers: Headers,
     pub body: Vec<u8>,
+    // the fields sent after a chunked body
+    pub trailers: Headers,
 }
 
 impl Default for HttpRequest {
@@ -71,6 +142,25 @@ impl Default for HttpRequest {
             version: Version::Unsupported,
             headers: Headers::new(),
             body: vec![],
+            trailers: Headers::new(),
+        }
+    }
+}
+
+impl HttpRequest {
+    // whether the client wants the connection kept open after the response, HTTP/1.1
+    // connections persist unless closed and HTTP/1.0 ones only when asked to
+    pub fn keep_alive(&self) -> bool {
+        let connection = self
+            .headers
+            .get_all(headers::CONNECTION)
+            .join(",")
+            .to_ascii_lowercase();
+        let has = |token| connection.split(',').any(|t| t.trim() == token);
+        match self.version {
+            Version::V11 => !has("close"),
+            Version::V10 => has("keep-alive"),
+            _ => false,
         }
     }
 }
@@ -87,62 +177,414 @@ pub(super) fn parse_req_line(req_line: String) -> errors::Result<(Method, Url, V
     }
 }
 
-fn read_req_line(buf_reader: &mut BufReader<&mut TcpStream>) -> errors::Result<HttpRequest> {
-    let mut req = HttpRequest::default();
-    let mut line = String::new();
-    let num_bytes = buf_reader.read_line(&mut line)?;
-    if num_bytes == 0 {
+// TimedStream is the reading side of a connection whose reads fail once a deadline has
+// passed. The socket timeout alone bounds each read, so a client sending a byte now and
+// then could hold the connection forever.
+pub(super) struct TimedStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+pub(super) type RequestReader = BufReader<TimedStream>;
+
+impl TimedStream {
+    pub(super) fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            deadline: None,
+        }
+    }
+
+    // fail the reads which end later than timeout from now, or never if it is None
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        if timeout.is_none() {
+            self.stream.set_read_timeout(None)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(left))?;
+        }
+        self.stream.read(buf)
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+// a request which is not received in time gets 408
+fn timed_out(err: ServerError) -> ServerError {
+    match err {
+        ServerError::IoError(err) if is_timeout(&err) => ServerError::RequestTimeout,
+        err => err,
+    }
+}
+
+// room left in the request line for the method and the version
+const REQ_LINE_SLACK: usize = 64;
+
+// read a line of at most limit bytes as they are, a line of bytes which are not
+// accepted is refused with 400 by its reader rather than failing the connection
+fn read_raw_line<R: BufRead>(
+    buf_reader: &mut R,
+    limit: usize,
+    line: &mut Vec<u8>,
+) -> io::Result<usize> {
+    buf_reader.take(limit as u64).read_until(b'\n', line)
+}
+
+// the text of a field value. Bytes outside of ASCII are taken as UTF-8, or else as
+// latin-1 like the obs-text of old clients, but the control characters are refused.
+fn field_value(value: &[u8]) -> errors::Result<String> {
+    if value
+        .iter()
+        .any(|&b| (b.is_ascii_control() && b != b'\t') || b == 0x7f)
+    {
         return Err(ServerError::PareRequestError);
     }
+    Ok(String::from_utf8(value.to_vec())
+        .unwrap_or_else(|_| value.iter().map(|&b| b as char).collect()))
+}
+
+fn read_req_line<R: BufRead>(
+    buf_reader: &mut R,
+    max_uri_len: usize,
+) -> errors::Result<HttpRequest> {
+    let mut req = HttpRequest::default();
+    let mut line = vec![];
+    let limit = max_uri_len + REQ_LINE_SLACK;
+    let num_bytes = match read_raw_line(buf_reader, limit, &mut line)? {
+        0 => return Err(ServerError::ConnectionClosed),
+        num_bytes => num_bytes,
+    };
+    // the line was cut before its end
+    if num_bytes == limit && !line.ends_with(b"\n") {
+        return Err(ServerError::UriTooLong(max_uri_len));
+    }
+    // the target is percent-encoded, nothing in the line is beyond ASCII
+    let line = String::from_utf8(line)
+        .ok()
+        .filter(|line| line.is_ascii())
+        .ok_or(ServerError::PareRequestError)?;
     let (method, url, version) = parse_req_line(line)?;
+    if url.get_raw().len() > max_uri_len {
+        return Err(ServerError::UriTooLong(max_uri_len));
+    }
     req.method = method;
     req.url = url;
     req.version = version;
     Ok(req)
 }
 
-fn read_headers(
-    buf_reader: &mut BufReader<&mut TcpStream>,
-    req: &mut HttpRequest,
+// read `name: value` lines up to an empty line, for the headers and the trailers. A
+// repeated name keeps all its values, and a line which is not a field fails the request
+// rather than being skipped, as the server and a proxy before it could read it
+// differently. The fields are bounded in size and in number.
+fn read_fields<R: BufRead>(
+    buf_reader: &mut R,
+    headers: &mut Headers,
+    config: &ServerConfig,
 ) -> errors::Result<()> {
-    let headers = req.headers.borrow_mut();
-    let mut line = String::new();
+    let too_large = ServerError::HeadersTooLarge(config.max_header_size, config.max_headers);
+    let (mut line, mut size) = (vec![], 0);
     loop {
-        let num_bytes = buf_reader.read_line(&mut line)?;
-        if num_bytes == 0 || line == "\r\n" {
+        let left = config.max_header_size - size;
+        let num_bytes = read_raw_line(buf_reader, left, &mut line)?;
+        size += num_bytes;
+        if num_bytes == left && !line.ends_with(b"\n") {
+            return Err(too_large);
+        }
+        if num_bytes == 0 || line == b"\r\n" || line == b"\n" {
             break;
         }
-        let parts: Vec<_> = line.splitn(2, ": ").collect();
-        if parts.len() == 2 {
-            let key = parts[0].trim().to_string();
-            let value = parts[1].trim().to_string();
-            headers.insert(key, value);
+        if headers.len() == config.max_headers {
+            return Err(too_large);
         }
+        let colon = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or(ServerError::PareRequestError)?;
+        let name = std::str::from_utf8(&line[..colon])
+            .ok()
+            .filter(|name| headers::is_valid_name(name))
+            .ok_or(ServerError::PareRequestError)?;
+        let value = line[colon + 1..].trim_ascii_end();
+        headers.append(name, field_value(value)?.trim());
         line.clear();
     }
     Ok(())
 }
 
-fn read_body(
-    buf_reader: &mut BufReader<&mut TcpStream>,
+// longest chunk size line accepted, extensions included
+const CHUNK_LINE_LIMIT: usize = 1024;
+
+// decode a chunked body into body, the trailers which follow the last chunk are read into
+// trailers
+fn read_chunked<R: BufRead>(
+    buf_reader: &mut R,
+    body: &mut Vec<u8>,
+    trailers: &mut Headers,
+    config: &ServerConfig,
+) -> errors::Result<()> {
+    let max_body_size = config.max_body_size;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        read_raw_line(buf_reader, CHUNK_LINE_LIMIT, &mut line)?;
+        if !line.ends_with(b"\n") {
+            return Err(ServerError::PareRequestError);
+        }
+        // the extensions after `;` are ignored
+        let size = line.split(|&b| b == b';').next().unwrap();
+        let size = size.trim_ascii();
+        // from_str_radix would accept a sign too
+        if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+            return Err(ServerError::PareRequestError);
+        }
+        let size = std::str::from_utf8(size).unwrap();
+        let size = usize::from_str_radix(size, 16).map_err(|_| ServerError::PareRequestError)?;
+        if size == 0 {
+            break;
+        }
+        // the size comes from the client, adding it to the body length could overflow
+        if size > max_body_size.saturating_sub(body.len()) {
+            return Err(ServerError::PayloadTooLarge(max_body_size));
+        }
+        let num_bytes = buf_reader.take(size as u64).read_to_end(body)?;
+        let mut crlf = [0; 2];
+        buf_reader.read_exact(&mut crlf)?;
+        if num_bytes < size || &crlf != b"\r\n" {
+            return Err(ServerError::PareRequestError);
+        }
+    }
+    read_fields(buf_reader, trailers, config)
+}
+
+fn read_body<R: BufRead>(
+    buf_reader: &mut R,
     req: &mut HttpRequest,
+    config: &ServerConfig,
 ) -> errors::Result<()> {
-    if let Some(len) = req.headers.get(headers::CONTENT_LENGTH) {
-        if let Ok(len) = len.parse::<usize>() {
-            req.body = vec![0; len];
-            buf_reader.read_exact(&mut req.body)?;
-            return Ok(());
-        } else {
+    let max_body_size = config.max_body_size;
+    // the transfer encoding takes precedence over the length
+    if req.headers.contains(headers::TRANSFER_ENCODING) {
+        // the codings may be spread over several fields, chunked has to be the last one
+        let encoding = req.headers.get_all(headers::TRANSFER_ENCODING).join(",");
+        let last = encoding.rsplit(',').next().unwrap_or_default();
+        if !last.trim().eq_ignore_ascii_case("chunked") {
             return Err(ServerError::PareRequestError);
         }
+        // a proxy before the server may have framed the message by its length instead,
+        // so the length is dropped and the connection closed after the response
+        if req.headers.contains(headers::CONTENT_LENGTH) {
+            req.headers.remove(headers::CONTENT_LENGTH);
+            req.headers.append(headers::CONNECTION, "close");
+        }
+        return read_chunked(buf_reader, &mut req.body, &mut req.trailers, config);
+    }
+    // a length repeated by several fields, or in a list, has to be the same everywhere
+    let lengths: Vec<_> = req
+        .headers
+        .get_all(headers::CONTENT_LENGTH)
+        .into_iter()
+        .flat_map(|len| len.split(','))
+        .map(|len| len.trim())
+        // parse would accept a sign too
+        .map(|len| {
+            let digits = !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit());
+            digits.then(|| len.parse::<usize>().ok()).flatten()
+        })
+        .collect();
+    let Some(first) = lengths.first() else {
+        return Ok(());
+    };
+    let Some(len) = *first else {
+        return Err(ServerError::PareRequestError);
+    };
+    if lengths.iter().any(|other| *other != Some(len)) {
+        return Err(ServerError::PareRequestError);
+    }
+    if len > max_body_size {
+        return Err(ServerError::PayloadTooLarge(max_body_size));
+    }
+    // the buffer grows with the bytes actually received rather than trusting the
+    // announced length
+    let num_bytes = buf_reader.take(len as u64).read_to_end(&mut req.body)?;
+    if num_bytes < len {
+        return Err(ServerError::PareRequestError);
     }
     Ok(())
 }
 
-pub(super) fn parse_request(stream: &mut TcpStream) -> errors::Result<HttpRequest> {
-    let mut buf_reader = BufReader::new(stream);
-    let mut req = read_req_line(&mut buf_reader)?;
-    read_headers(&mut buf_reader, &mut req)?;
-    read_body(&mut buf_reader, &mut req)?;
+// parse the next request of the connection, the reader lives as long as the connection
+// so that the bytes of pipelined requests it buffered are not lost
+pub(super) fn parse_request(
+    buf_reader: &mut RequestReader,
+    config: &ServerConfig,
+) -> errors::Result<HttpRequest> {
+    // wait for the request to start, the connection is closed quietly if it stays idle
+    buf_reader
+        .get_mut()
+        .set_timeout(Some(config.idle_timeout))?;
+    match buf_reader.fill_buf() {
+        Ok([]) => return Err(ServerError::ConnectionClosed),
+        Ok(_) => {}
+        Err(err) if is_timeout(&err) => return Err(ServerError::ConnectionClosed),
+        Err(err) => return Err(err.into()),
+    }
+    // then the head and the body have to arrive in time, however slowly they come
+    buf_reader
+        .get_mut()
+        .set_timeout(Some(config.header_timeout))?;
+    let mut req = read_req_line(buf_reader, config.max_uri_len).map_err(timed_out)?;
+    read_fields(buf_reader, &mut req.headers, config).map_err(timed_out)?;
+    buf_reader
+        .get_mut()
+        .set_timeout(Some(config.body_timeout))?;
+    read_body(buf_reader, &mut req, config).map_err(timed_out)?;
+    buf_reader.get_mut().set_timeout(None)?;
     Ok(req)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        let url = Url::new("/a%20b/c%2Fd?ttl=10&tag=x+y&tag=%26&flag");
+        assert_eq!(url.path(), "/a%20b/c%2Fd");
+        assert_eq!(url.query("ttl"), Some("10"));
+        assert_eq!(url.query_all("tag"), vec!["x y", "&"]);
+        assert_eq!(url.query("flag"), Some(""));
+        assert_eq!(url.query("missing"), None);
+        assert_eq!(percent_decode("100%+1%2", false), "100%+1%2");
+    }
+
+    #[test]
+    fn test_read_fields() {
+        let raw = "content-length:5\r\nAccept: a\r\naccept: b\r\n\r\n";
+        let mut headers = Headers::new();
+        let config = ServerConfig::default();
+        read_fields(&mut Cursor::new(raw.as_bytes()), &mut headers, &config).unwrap();
+        assert_eq!(headers.get(headers::CONTENT_LENGTH), Some("5".to_string()));
+        assert_eq!(headers.get_all("ACCEPT"), vec!["a", "b"]);
+
+        // the bytes beyond ASCII are kept, the control characters refused
+        let raw = b"Utf8: caf\xc3\xa9\r\nLatin1: caf\xe9\r\n\r\n";
+        let mut headers = Headers::new();
+        read_fields(&mut Cursor::new(&raw[..]), &mut headers, &config).unwrap();
+        assert_eq!(headers.get("Utf8"), Some("café".to_string()));
+        assert_eq!(headers.get("Latin1"), Some("café".to_string()));
+        let raw = b"A: x\x01y\r\n\r\n";
+        let result = read_fields(&mut Cursor::new(&raw[..]), &mut Headers::new(), &config);
+        assert!(matches!(result, Err(ServerError::PareRequestError)));
+        let raw = b"GET /caf\xe9 HTTP/1.1\r\n";
+        let result = read_req_line(&mut Cursor::new(&raw[..]), 64);
+        assert!(matches!(result, Err(ServerError::PareRequestError)));
+
+        let raw = "Bad Name: x\r\n\r\n";
+        let result = read_fields(
+            &mut Cursor::new(raw.as_bytes()),
+            &mut Headers::new(),
+            &config,
+        );
+        assert!(result.is_err());
+
+        let raw = "A: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        let config = ServerConfig {
+            max_headers: 2,
+            ..config
+        };
+        let result = read_fields(
+            &mut Cursor::new(raw.as_bytes()),
+            &mut Headers::new(),
+            &config,
+        );
+        assert!(matches!(result, Err(ServerError::HeadersTooLarge(..))));
+        let config = ServerConfig {
+            max_header_size: 8,
+            ..config
+        };
+        let result = read_fields(
+            &mut Cursor::new(raw.as_bytes()),
+            &mut Headers::new(),
+            &config,
+        );
+        assert!(matches!(result, Err(ServerError::HeadersTooLarge(..))));
+    }
+
+    #[test]
+    fn test_read_chunked() {
+        let raw = "5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: 42\r\n\r\nGET";
+        let mut reader = Cursor::new(raw.as_bytes());
+        let (mut body, mut trailers) = (vec![], Headers::new());
+        let config = ServerConfig {
+            max_body_size: 64,
+            ..Default::default()
+        };
+        read_chunked(&mut reader, &mut body, &mut trailers, &config).unwrap();
+        assert_eq!(body, b"hello world");
+        assert_eq!(trailers.get("Checksum"), Some("42".to_string()));
+        // the next request is left in the reader
+        assert_eq!(reader.position() as usize, raw.len() - 3);
+
+        let mut reader = Cursor::new(raw.as_bytes());
+        let config = ServerConfig {
+            max_body_size: 8,
+            ..config
+        };
+        let result = read_chunked(&mut reader, &mut vec![], &mut Headers::new(), &config);
+        assert!(matches!(result, Err(ServerError::PayloadTooLarge(8))));
+
+        let raw = "5\r\nhello\r\nffffffffffffffff\r\n";
+        let mut reader = Cursor::new(raw.as_bytes());
+        let result = read_chunked(&mut reader, &mut vec![], &mut Headers::new(), &config);
+        assert!(matches!(result, Err(ServerError::PayloadTooLarge(8))));
+
+        let raw = "+5\r\nhello\r\n0\r\n\r\n";
+        let mut reader = Cursor::new(raw.as_bytes());
+        let result = read_chunked(&mut reader, &mut vec![], &mut Headers::new(), &config);
+        assert!(matches!(result, Err(ServerError::PareRequestError)));
+    }
+
+    #[test]
+    fn test_read_body() {
+        let config = ServerConfig::default();
+        let mut req = HttpRequest {
+            version: Version::V11,
+            ..Default::default()
+        };
+        req.headers.put(headers::CONTENT_LENGTH, "+2");
+        let result = read_body(&mut Cursor::new(&b"ab"[..]), &mut req, &config);
+        assert!(matches!(result, Err(ServerError::PareRequestError)));
+
+        // a message framed both ways is read as chunked, and closes the connection
+        req.headers.put(headers::CONTENT_LENGTH, "3");
+        req.headers.put(headers::TRANSFER_ENCODING, "chunked");
+        assert!(req.keep_alive());
+        read_body(
+            &mut Cursor::new(&b"2\r\nab\r\n0\r\n\r\n"[..]),
+            &mut req,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(req.body, b"ab");
+        assert!(!req.keep_alive());
+    }
+}
@@ -3,14 +3,18 @@
 use std::{
     io::{self, Write},
     net::TcpStream,
+    time::Instant,
 };
 
-use super::{headers::Headers, status, version::Version};
+use super::{
+    headers::{self, Headers},
+    status::{self, StatusCode},
+    version::Version,
+};
 
 pub struct HttpResponse {
     pub version: Version,
-    pub status_code: u32,
-    pub status_text: String,
+    pub status_code: StatusCode,
     pub headers: Headers,
     pub body: Option<Vec<u8>>,
 }
@@ -19,8 +23,7 @@ impl Default for HttpResponse {
     fn default() -> Self {
         Self {
             version: Version::V11,
-            status_code: 200,
-            status_text: "OK".to_string(),
+            status_code: status::OK,
             headers: Headers::new(),
             body: None,
         }
@@ -30,57 +33,110 @@ impl Default for HttpResponse {
 impl HttpResponse {
     pub fn new(
         version: Version,
-        status_code: u32,
-        status_text: String,
+        status_code: StatusCode,
         headers: Headers,
         body: Option<Vec<u8>>,
     ) -> Self {
         Self {
             version,
             status_code,
-            status_text,
             headers,
             body,
         }
     }
 
-    fn get_status_text(status_code: u32) -> String {
-        match status_code {
-            status::OK => "OK".to_string(),
-            status::BAD_REQUEST => "Bad Request".to_string(),
-            status::UNAUTHORIZED => "Unauthorized".to_string(),
-            status::FORBIDDEN => "Forbidden".to_string(),
-            _ => "Not Found".to_string(),
-        }
-    }
-
-    pub fn status(&mut self, status_code: u32) -> &mut Self {
+    pub fn status(&mut self, status_code: StatusCode) -> &mut Self {
         self.status_code = status_code;
-        self.status_text = Self::get_status_text(status_code);
         self
     }
 
+    // set the header, replacing its values
     pub fn add_header(&mut self, key: &str, value: &str) -> &mut Self {
         self.headers.put(key, value);
         self
     }
 
+    // add a value to the header, keeping its other values, as for Set-Cookie
+    pub fn append_header(&mut self, key: &str, value: &str) -> &mut Self {
+        self.headers.append(key, value);
+        self
+    }
+
     pub fn body(&mut self, body: Vec<u8>) -> &mut Self {
         self.body = Some(body);
         self
     }
 }
 
-pub(crate) fn write_response(stream: &mut TcpStream, res: &HttpResponse) -> io::Result<()> {
+// bodies up to this size are sent in the same write as the head
+const INLINE_BODY: usize = 64 * 1024;
+
+// write the head, and the body if any, with as few writes as possible. A head written on
+// its own and followed by a small write would wait for the client's delayed ack before
+// the rest is sent.
+pub(crate) fn write_response(stream: &mut impl Write, res: &HttpResponse) -> io::Result<()> {
     let version: String = res.version.into();
-    let req_line = format!("{} {} {}\r\n", version, res.status_code, res.status_text);
-    stream.write(req_line.as_bytes())?;
+    let req_line = format!(
+        "{} {} {}\r\n",
+        version,
+        res.status_code,
+        res.status_code.reason()
+    );
+    // a field holding a line break could add fields, or a body, to the response
+    for (key, value) in res.headers.iter() {
+        if !headers::is_valid_name(key) || !headers::is_valid_value(value) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid response header {:?}", key),
+            ));
+        }
+    }
+    let mut head = req_line.into_bytes();
     for (key, value) in res.headers.iter() {
-        stream.write(format!("{}: {}\r\n", key, value).as_bytes())?;
+        head.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
+    }
+    head.extend_from_slice(b"\r\n");
+    match res.body {
+        Some(ref body) if body.len() <= INLINE_BODY => {
+            head.extend_from_slice(body);
+            stream.write_all(&head)?;
+        }
+        Some(ref body) => {
+            stream.write_all(&head)?;
+            stream.write_all(body)?;
+        }
+        None => stream.write_all(&head)?,
+    }
+    stream.flush()
+}
+
+// TimedWriter is the writing side of a connection whose writes fail once a deadline has
+// passed. The socket timeout alone bounds each write, so a client reading a byte now and
+// then could hold a response forever.
+pub(super) struct TimedWriter<'a> {
+    stream: &'a mut TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl<'a> TimedWriter<'a> {
+    pub(super) fn new(stream: &'a mut TcpStream, deadline: Option<Instant>) -> Self {
+        Self { stream, deadline }
+    }
+}
+
+impl Write for TimedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.stream.set_write_timeout(Some(left))?;
+        }
+        self.stream.write(buf)
     }
-    stream.write("\r\n".as_bytes())?;
-    if let Some(ref body) = res.body {
-        stream.write(body)?;
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
     }
-    Ok(())
 }
@@ -1,23 +1,28 @@
 #![allow(dead_code)]
 
 use std::{
-    cell::RefCell,
     collections::HashMap,
+    io,
     ops::{Deref, DerefMut},
     ptr::NonNull,
-    rc::Rc,
 };
 
 use super::{
     errors::{self, ServerError},
-    method::Method,
-    request::{ParamsMap, Url},
-    status, Context,
+    headers,
+    request::{percent_decode, ParamsMap, Url},
+    status, Context, Method,
 };
 
 pub type HandleResult = errors::Result<()>;
-pub type HandlerFunc = dyn Fn(&mut Context) -> HandleResult + Send + 'static;
+pub type HandlerFunc = dyn Fn(&mut Context) -> HandleResult + Send + Sync + 'static;
 pub type BoxedHandlerFunc = Box<HandlerFunc>;
+// Next runs the rest of the chain, the remaining middlewares and then the handler
+pub type Next<'a> = &'a dyn Fn(&mut Context) -> HandleResult;
+// a middleware wraps the handling of a request, it may change the context before or
+// after calling next, or answer the request itself without calling it
+pub type MiddlewareFunc = dyn Fn(&mut Context, Next) -> HandleResult + Send + Sync + 'static;
+pub type BoxedMiddlewareFunc = Box<MiddlewareFunc>;
 
 type RouterMap = HashMap<String, WrappedLink>;
 type Link = NonNull<RouterNode>;
@@ -32,6 +37,8 @@ impl WrappedLink {
 }
 
 unsafe impl Send for WrappedLink {}
+// the nodes are only mutated while binding, which requires exclusive access to the router
+unsafe impl Sync for WrappedLink {}
 
 impl Deref for WrappedLink {
     type Target = RouterNode;
@@ -46,17 +53,30 @@ impl DerefMut for WrappedLink {
     }
 }
 
+#[derive(Default)]
 struct RouterNode {
     pub part: String,
+    // the children matching a segment exactly
     pub next_node_map: RouterMap,
+    // the children matching any segment, and any remaining segments. The param names
+    // are kept by the routes rather than by these nodes, so that routes naming their
+    // params differently share them.
+    pub param_link: Option<WrappedLink>,
+    pub wildcard_link: Option<WrappedLink>,
     pub handler: Option<BoxedHandlerFunc>,
+    // the url the handler is bound to and the names of its params and wildcard, in
+    // the order of the segments they match
+    pub pattern: String,
+    pub param_names: Vec<String>,
     pub is_param: bool,
+    pub is_wildcard: bool,
 }
 
 impl RouterNode {
     fn new(part: &str) -> Self {
         let is_param = part.starts_with(':');
-        let part = if is_param {
+        let is_wildcard = part.starts_with('*');
+        let part = if is_param || is_wildcard {
             part[1..].to_owned()
         } else {
             part.to_owned()
@@ -64,8 +84,13 @@ impl RouterNode {
         Self {
             part,
             next_node_map: HashMap::new(),
+            param_link: None,
+            wildcard_link: None,
             handler: None,
+            pattern: String::new(),
+            param_names: vec![],
             is_param,
+            is_wildcard,
         }
     }
 
@@ -81,45 +106,57 @@ impl RouterNode {
         (self.handler.as_ref().unwrap())(ctx)
     }
 
-    fn get_all_param_links(&self) -> Vec<WrappedLink> {
-        let param_links: Vec<_> = self
-            .next_node_map
-            .values()
-            .into_iter()
-            .filter(|l| l.is_param)
-            .map(|l| *l)
-            .collect();
-        param_links
-    }
-
     fn get_route_link(&self, next_part: &str) -> Option<WrappedLink> {
         self.next_node_map.get(next_part).copied()
     }
 
+    // get the child for the part of a route being bound, creating it if it is missing
+    fn get_or_create_link(&mut self, part: &str) -> WrappedLink {
+        let node = RouterNode::new(part);
+        let link = match (node.is_param, node.is_wildcard) {
+            (true, _) => &mut self.param_link,
+            (_, true) => &mut self.wildcard_link,
+            _ => {
+                return *self
+                    .next_node_map
+                    .entry(part.to_owned())
+                    .or_insert_with(|| node.wrap())
+            }
+        };
+        *link.get_or_insert_with(|| node.wrap())
+    }
+
     // wrap will take the ownership of a router node and return the wrapped NonNull<RouterNode>
     fn wrap(self) -> WrappedLink {
         WrappedLink::new(unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(self))) })
     }
 }
 
-impl Default for RouterNode {
-    fn default() -> Self {
-        Self {
-            part: String::new(),
-            next_node_map: HashMap::new(),
-            handler: None,
-            is_param: false,
-        }
-    }
-}
-
 pub struct Router {
     root: WrappedLink,
+    // the middlewares with the url prefix they apply to, in the order they run
+    middlewares: Vec<(String, BoxedMiddlewareFunc)>,
+    // the origins allowed to make cross-origin requests, `*` allows any
+    cors_origins: Vec<String>,
 }
 
+// the methods which can be bound, in the order they are listed by the Allow header
+const METHODS: [Method; 7] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Patch,
+    Method::Delete,
+    Method::Options,
+];
+
+// how long browsers may cache the answer to a preflight request, in seconds
+const CORS_MAX_AGE: &str = "86400";
+
 impl Router {
     fn add_method_nodes(root: &mut RouterNode) {
-        for method in vec![Method::Get, Method::Post, Method::Put, Method::Delete].into_iter() {
+        for method in METHODS {
             let node = RouterNode::new(method.into());
             root.add_router_link(method.into(), node.wrap());
         }
@@ -128,7 +165,11 @@ impl Router {
     pub fn new() -> Self {
         let mut root = RouterNode::default();
         Self::add_method_nodes(&mut root);
-        Router { root: root.wrap() }
+        Router {
+            root: root.wrap(),
+            middlewares: vec![],
+            cors_origins: vec![],
+        }
     }
 
     fn get_parts(url: &str) -> Vec<&str> {
@@ -143,132 +184,679 @@ impl Router {
     }
 
     fn url_with_method(url: &str, method: Method) -> String {
-        let url = if url.starts_with('/') { &url[1..] } else { url };
+        let url = url.strip_prefix('/').unwrap_or(url);
         let method: &str = method.into();
         format!("{}/{}", method, url)
     }
 
+    // the parts of a route, the method followed by the segments of the path, which are
+    // decoded only once split so that an encoded slash stays in its segment
+    fn route_parts(path: &str, method: Method) -> Vec<String> {
+        let url_with_method = Self::url_with_method(path, method);
+        Self::get_parts(&url_with_method)
+            .into_iter()
+            .map(|part| percent_decode(part, false))
+            .collect()
+    }
+
+    // find the node bound to the parts, trying the static children first, then the
+    // param child and last the wildcard one, which takes all the remaining parts. The
+    // segments matched by params are pushed to values.
     fn _search_route_node(
         from: WrappedLink,
-        parts: &[&str],
-        create: bool,
-        params: Option<Rc<RefCell<ParamsMap>>>,
+        parts: &[String],
+        values: &mut Vec<String>,
     ) -> Option<WrappedLink> {
-        let mut cur_node = from;
-        for (i, part) in parts.iter().enumerate() {
-            // check static link
-            if let Some(node) = cur_node.get_route_link(part) {
-                cur_node = node;
-                continue;
-            }
-            // check param links
-            for next in cur_node.get_all_param_links() {
-                let result =
-                    Self::_search_route_node(next, &parts[i + 1..], create, params.clone());
-                if result.is_some() {
-                    if let Some(params) = params {
-                        params
-                            .as_ref()
-                            .borrow_mut()
-                            .insert(next.part.clone(), part.to_string());
-                    }
-                    return result;
-                }
+        let Some((part, rest)) = parts.split_first() else {
+            return from.handler.is_some().then_some(from);
+        };
+        if let Some(next) = from.get_route_link(part) {
+            if let Some(node) = Self::_search_route_node(next, rest, values) {
+                return Some(node);
             }
-            // if don't create new node, return
-            if !create {
-                return None;
+        }
+        if let Some(next) = from.param_link {
+            values.push(part.clone());
+            if let Some(node) = Self::_search_route_node(next, rest, values) {
+                return Some(node);
             }
-            let new_node = RouterNode::new(&part).wrap();
-            cur_node.add_router_link(part, new_node);
-            cur_node = new_node;
+            values.pop();
         }
-        if create || cur_node.handler.is_some() {
-            Some(cur_node)
-        } else {
-            None
+        match from.wildcard_link {
+            Some(next) if next.handler.is_some() => {
+                values.push(parts.join("/"));
+                Some(next)
+            }
+            _ => None,
         }
     }
 
-    fn search_and_create_route_node(&self, parts: &[&str]) -> Option<WrappedLink> {
-        Self::_search_route_node(self.root, parts, true, None)
+    fn search_and_create_route_node(&self, parts: &[String]) -> WrappedLink {
+        parts
+            .iter()
+            .fold(self.root, |mut node, part| node.get_or_create_link(part))
     }
 
-    fn search_route_node(&self, parts: &[&str]) -> Option<WrappedLink> {
-        Self::_search_route_node(self.root, parts, false, None)
+    fn search_route_node(&self, parts: &[String]) -> Option<WrappedLink> {
+        Self::_search_route_node(self.root, parts, &mut vec![])
     }
 
-    fn search_route_node_with_params(&self, parts: &[&str]) -> (Option<WrappedLink>, ParamsMap) {
-        let rc = Rc::new(RefCell::new(ParamsMap::new()));
-        (
-            Self::_search_route_node(self.root, parts, false, Some(rc.clone())),
-            rc.as_ref().to_owned().into_inner(),
-        )
+    fn search_route_node_with_params(&self, parts: &[String]) -> (Option<WrappedLink>, ParamsMap) {
+        let mut values = vec![];
+        let node = Self::_search_route_node(self.root, parts, &mut values);
+        let params = match node {
+            Some(node) => node.param_names.iter().cloned().zip(values).collect(),
+            None => ParamsMap::new(),
+        };
+        (node, params)
+    }
+
+    // the methods the url can be requested with, HEAD is served by the GET handler if
+    // it is not bound, and OPTIONS is answered for any url which is bound
+    fn allowed_methods(&self, url: &str) -> Vec<Method> {
+        let bound: Vec<_> = METHODS
+            .into_iter()
+            .filter(|method| {
+                self.search_route_node(&Self::route_parts(url, *method))
+                    .is_some()
+            })
+            .collect();
+        METHODS
+            .into_iter()
+            .filter(|method| match method {
+                _ if bound.contains(method) => true,
+                Method::Head => bound.contains(&Method::Get),
+                Method::Options => !bound.is_empty(),
+                _ => false,
+            })
+            .collect()
     }
 
-    fn bind<F>(&mut self, url: &Url, method: Method, handler: F) -> &mut Self
+    // bind the handler to the url, which is made of static segments, `:name` params
+    // matching a segment and a last `*name` wildcard matching the remaining segments.
+    // Binding fails if a bound url matches the same requests, as they could not be
+    // told apart.
+    pub fn try_bind<F>(
+        &mut self,
+        url: &Url,
+        method: Method,
+        handler: F,
+    ) -> errors::Result<&mut Self>
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
-        let url_with_method = Self::url_with_method(url.get_raw(), method);
-        let parts = Self::get_parts(&url_with_method);
-        let mut node = self.search_and_create_route_node(&parts).unwrap();
-        node.bind_handler(Box::new(handler));
-        self
+        self.bind_boxed(url, method, Box::new(handler))?;
+        Ok(self)
+    }
+
+    fn bind_boxed(
+        &mut self,
+        url: &Url,
+        method: Method,
+        handler: BoxedHandlerFunc,
+    ) -> errors::Result<()> {
+        let parts = Self::route_parts(url.path(), method);
+        let method: &str = method.into();
+        if let Some(i) = parts.iter().position(|part| part.starts_with('*')) {
+            if i + 1 != parts.len() {
+                return Err(ServerError::RouteConflict(format!(
+                    "{} {} has a wildcard before its last segment",
+                    method,
+                    url.path()
+                )));
+            }
+        }
+        let mut node = self.search_and_create_route_node(&parts);
+        if node.handler.is_some() {
+            return Err(ServerError::RouteConflict(format!(
+                "{} {} matches the same urls as {}",
+                method,
+                url.path(),
+                node.pattern
+            )));
+        }
+        node.param_names = parts
+            .iter()
+            .filter(|part| part.starts_with([':', '*']))
+            .map(|part| part[1..].to_owned())
+            .collect();
+        node.pattern = url.path().to_owned();
+        node.bind_handler(handler);
+        Ok(())
+    }
+
+    // the routes are bound once at startup, so a conflict is a bug and panics
+    pub fn bind<F>(&mut self, url: &Url, method: Method, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.try_bind(url, method, handler)
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
     pub fn bind_get<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
         self.bind(url, Method::Get, handler)
     }
 
+    // a HEAD request is served by the GET handler unless one is bound for it
+    pub fn bind_head<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.bind(url, Method::Head, handler)
+    }
+
     pub fn bind_put<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
         self.bind(url, Method::Put, handler)
     }
 
+    pub fn bind_patch<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.bind(url, Method::Patch, handler)
+    }
+
     pub fn bind_post<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
         self.bind(url, Method::Post, handler)
     }
 
     pub fn bind_delete<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
         self.bind(url, Method::Delete, handler)
     }
 
-    pub fn route(
-        &mut self,
-        url: &Url,
-        method: Method,
+    // an OPTIONS request is answered with the allowed methods unless one is bound for it
+    pub fn bind_options<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.bind(url, Method::Options, handler)
+    }
+
+    // allow the origin to make cross-origin requests, `*` allows any origin
+    pub fn allow_origin(&mut self, origin: &str) -> &mut Self {
+        self.cors_origins.push(origin.to_owned());
+        self
+    }
+
+    // the value of the Access-Control-Allow-Origin header for the request, if its
+    // origin is allowed
+    fn cors_origin(&self, ctx: &Context) -> Option<String> {
+        let origin = ctx.req.headers.get(headers::ORIGIN)?;
+        if self.cors_origins.iter().any(|allowed| allowed == "*") {
+            Some("*".to_owned())
+        } else {
+            self.cors_origins.contains(&origin).then_some(origin)
+        }
+    }
+
+    // answer an OPTIONS request with the allowed methods, and if it is a preflight
+    // request from an allowed origin, with what the actual request may use
+    fn answer_options(&self, ctx: &mut Context, allow: &str) {
+        ctx.status(status::NO_CONTENT);
+        ctx.res.add_header(headers::ALLOW, allow);
+        let req = &ctx.req;
+        if req
+            .headers
+            .get(headers::ACCESS_CONTROL_REQUEST_METHOD)
+            .is_none()
+            || self.cors_origin(ctx).is_none()
+        {
+            return;
+        }
+        let request_headers = req.headers.get(headers::ACCESS_CONTROL_REQUEST_HEADERS);
+        ctx.res
+            .add_header(headers::ACCESS_CONTROL_ALLOW_METHODS, allow)
+            .add_header(headers::ACCESS_CONTROL_MAX_AGE, CORS_MAX_AGE);
+        if let Some(request_headers) = request_headers {
+            ctx.res
+                .add_header(headers::ACCESS_CONTROL_ALLOW_HEADERS, &request_headers);
+        }
+    }
+
+    // move the routes of the router under the prefix, along with its middlewares, which
+    // then only run for the urls under the prefix. Mounting fails on the first route
+    // conflicting with a bound one, the routes moved before it stay bound.
+    pub fn try_mount(&mut self, prefix: &str, mut router: Router) -> errors::Result<&mut Self> {
+        let prefix = prefix.trim_end_matches('/');
+        for (method, pattern, handler) in router.take_routes() {
+            let url = Url::new(&format!("{}{}", prefix, pattern));
+            self.bind_boxed(&url, method, handler)?;
+        }
+        for (sub_prefix, middleware) in router.middlewares.drain(..) {
+            let prefix = format!("{}{}", prefix, sub_prefix);
+            self.middlewares.push((prefix, middleware));
+        }
+        Ok(self)
+    }
+
+    // the routers are mounted once at startup, so a conflict is a bug and panics
+    pub fn mount(&mut self, prefix: &str, router: Router) -> &mut Self {
+        self.try_mount(prefix, router)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // take the handlers out of the router with the method and url they are bound to
+    fn take_routes(&mut self) -> Vec<(Method, String, BoxedHandlerFunc)> {
+        let mut routes = vec![];
+        for method in METHODS {
+            let mut nodes: Vec<_> = self
+                .root
+                .get_route_link(method.into())
+                .into_iter()
+                .collect();
+            while let Some(mut node) = nodes.pop() {
+                if let Some(handler) = node.handler.take() {
+                    routes.push((method, node.pattern.clone(), handler));
+                }
+                nodes.extend(node.next_node_map.values().copied());
+                nodes.extend(node.param_link);
+                nodes.extend(node.wildcard_link);
+            }
+        }
+        routes
+    }
+
+    // add a middleware running for every request, including the unrouted ones
+    pub fn add_middleware<F>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(&mut Context, Next) -> HandleResult + Send + Sync + 'static,
+    {
+        self.add_prefix_middleware("/", middleware)
+    }
+
+    // add a middleware running for the requests whose url is the prefix or below it
+    pub fn add_prefix_middleware<F>(&mut self, prefix: &str, middleware: F) -> &mut Self
+    where
+        F: Fn(&mut Context, Next) -> HandleResult + Send + Sync + 'static,
+    {
+        let prefix = prefix.trim_end_matches('/');
+        self.middlewares
+            .push((prefix.to_owned(), Box::new(middleware)));
+        self
+    }
+
+    // whether the path is the prefix or below it, "/ns" covers "/ns/a" but not "/nsa".
+    // The segments of the path are decoded as they are for dispatching, so that "/%6Es/a"
+    // is under "/ns" too.
+    fn under_prefix(segments: &[String], prefix: &str) -> bool {
+        let prefix: Vec<_> = prefix.split('/').collect();
+        segments.len() >= prefix.len() && prefix.iter().zip(segments).all(|(p, s)| p == s)
+    }
+
+    // run the middlewares one inside the other, with the handler innermost
+    fn run_chain(
+        middlewares: &[&BoxedMiddlewareFunc],
+        handler: Next,
         ctx: &mut Context,
-    ) -> Result<(), ServerError> {
-        let url_with_method = Self::url_with_method(url.get_raw(), method);
-        let parts = Self::get_parts(&url_with_method);
-        let (node, params) = self.search_route_node_with_params(&parts);
-        let result = match node {
+    ) -> HandleResult {
+        match middlewares.split_first() {
+            Some((middleware, rest)) => middleware(ctx, &|ctx: &mut Context| {
+                Self::run_chain(rest, handler, ctx)
+            }),
+            None => handler(ctx),
+        }
+    }
+
+    pub fn route(&self, url: &Url, method: Method, ctx: &mut Context) -> Result<(), ServerError> {
+        let segments: Vec<_> = url
+            .path()
+            .split('/')
+            .map(|segment| percent_decode(segment, false))
+            .collect();
+        let middlewares: Vec<_> = self
+            .middlewares
+            .iter()
+            .filter(|(prefix, _)| Self::under_prefix(&segments, prefix))
+            .map(|(_, middleware)| middleware)
+            .collect();
+        if let Some(origin) = self.cors_origin(ctx) {
+            ctx.res
+                .add_header(headers::ACCESS_CONTROL_ALLOW_ORIGIN, &origin)
+                .add_header(headers::VARY, headers::ORIGIN);
+        }
+        // the headers set before the handlers run, kept for answering on their behalf
+        let preset = ctx.res.headers.clone();
+        let dispatch = |ctx: &mut Context| self.dispatch(url, method, ctx);
+        let result = Self::run_chain(&middlewares, &dispatch, ctx);
+        // a handler, or a middleware, which failed before answering leaves the
+        // request unserved, and the headers it set belong to the answer it did not send
+        if let Err(err) = &result {
+            let unrouted = matches!(
+                err,
+                ServerError::RouteError(_) | ServerError::MethodNotAllowed(..)
+            );
+            if !unrouted && !ctx.has_written {
+                ctx.res.headers = preset.clone();
+                ctx.status(status::INTERNAL_ERROR);
+            }
+        }
+        // we should at least return the response headers.
+        if !ctx.has_written {
+            if let Err(err) = ctx.write_empty() {
+                // a header left by the handler holds a line break
+                if err.kind() != io::ErrorKind::InvalidInput {
+                    return Err(err.into());
+                }
+                ctx.res.headers = preset;
+                ctx.status(status::INTERNAL_ERROR);
+                ctx.write_empty()?;
+            }
+        }
+        if result.is_err() {
+            ctx.abort_chunks();
+        }
+        ctx.end_chunks()?;
+        result
+    }
+
+    // call the handler bound to the url and method
+    fn dispatch(&self, url: &Url, method: Method, ctx: &mut Context) -> HandleResult {
+        let parts = Self::route_parts(url.path(), method);
+        let (mut node, mut params) = self.search_route_node_with_params(&parts);
+        if node.is_none() && method == Method::Head {
+            let parts = Self::route_parts(url.path(), Method::Get);
+            (node, params) = self.search_route_node_with_params(&parts);
+        }
+        match node {
             Some(node) => {
                 ctx.req.url.set_params(params);
                 node.handle(ctx)
             }
             None => {
-                // set the status code to 404 NOT FOUND
-                ctx.status(status::NOT_FOUND);
-                Err(ServerError::RouteError(url.get_raw().to_owned()))
+                let allowed = self.allowed_methods(url.path());
+                let allow: Vec<&str> = allowed.iter().map(|&method| method.into()).collect();
+                if allowed.is_empty() {
+                    ctx.status(status::NOT_FOUND);
+                    Err(ServerError::RouteError(url.path().to_owned()))
+                } else if method == Method::Options {
+                    self.answer_options(ctx, &allow.join(", "));
+                    Ok(())
+                } else {
+                    // the url exists, but is not bound to this method
+                    ctx.status(status::METHOD_NOT_ALLOWED);
+                    ctx.res.add_header(headers::ALLOW, &allow.join(", "));
+                    let method: &str = method.into();
+                    Err(ServerError::MethodNotAllowed(
+                        method.to_owned(),
+                        url.path().to_owned(),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use super::*;
+    use crate::server::{version::Version, HttpRequest, HttpResponse, ServerConfig};
+
+    // route a request and return the raw response
+    fn request(router: &Router, method: Method, url: &str) -> String {
+        send(
+            router,
+            HttpRequest {
+                method,
+                url: Url::new(url),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn send(router: &Router, req: HttpRequest) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        {
+            let (url, method) = (req.url.clone(), req.method);
+            let mut ctx = Context::new(req, HttpResponse::default(), &mut stream);
+            let _ = router.route(&url, method, &mut ctx);
+        }
+        drop(stream);
+        let mut res = String::new();
+        client.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_middleware() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/ns/:key"), |c| Ok(c.write_text("value")?));
+        router.bind_get(&Url::new("/other"), |c| Ok(c.write_text("other")?));
+        let outer = log.clone();
+        router.add_middleware(move |c, next| {
+            outer.lock().unwrap().push("outer before");
+            let result = next(c);
+            outer.lock().unwrap().push("outer after");
+            result
+        });
+        let inner = log.clone();
+        router.add_prefix_middleware("/ns", move |c, next| {
+            inner.lock().unwrap().push("inner");
+            match c.req.headers.get("Authorization") {
+                Some(_) => next(c),
+                None => {
+                    c.status(status::UNAUTHORIZED);
+                    Ok(())
+                }
             }
+        });
+
+        assert!(request(&router, Method::Get, "/ns/a").starts_with("HTTP/1.1 401"));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["outer before", "inner", "outer after"]
+        );
+        // an encoded prefix reaches the same handler, so it runs the same middlewares
+        log.lock().unwrap().clear();
+        assert!(request(&router, Method::Get, "/%6Es/a").starts_with("HTTP/1.1 401"));
+        assert_eq!(log.lock().unwrap().len(), 3);
+        log.lock().unwrap().clear();
+        assert!(request(&router, Method::Get, "/nsa").starts_with("HTTP/1.1 404"));
+        assert_eq!(log.lock().unwrap().len(), 2);
+        log.lock().unwrap().clear();
+        assert!(request(&router, Method::Get, "/other").ends_with("other"));
+        assert_eq!(*log.lock().unwrap(), vec!["outer before", "outer after"]);
+        // the middlewares run for the unrouted requests too
+        log.lock().unwrap().clear();
+        assert!(request(&router, Method::Get, "/missing").starts_with("HTTP/1.1 404"));
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_precedence() {
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/:key"), |c| Ok(c.write_text("param")?));
+        router.bind_get(&Url::new("/list/len"), |c| Ok(c.write_text("static")?));
+        router.bind_get(&Url::new("/list/:key"), |c| Ok(c.write_text("list")?));
+        router.bind_get(&Url::new("/*path"), |c| {
+            Ok(c.write_text(c.req.url.clone().get_param("path").unwrap())?)
+        });
+        assert!(request(&router, Method::Get, "/list").ends_with("param"));
+        assert!(request(&router, Method::Get, "/list/len").ends_with("static"));
+        assert!(request(&router, Method::Get, "/list/a").ends_with("list"));
+        // the static and param routes do not match, so the wildcard takes the rest
+        assert!(request(&router, Method::Get, "/users/42/profile").ends_with("users/42/profile"));
+        assert!(request(&router, Method::Get, "/list/a%2Fb/c").ends_with("list/a/b/c"));
+
+        let ok = |_: &mut Context| Ok(());
+        assert!(router
+            .try_bind(&Url::new("/:name"), Method::Get, ok)
+            .is_err());
+        assert!(router
+            .try_bind(&Url::new("/*rest"), Method::Get, ok)
+            .is_err());
+        assert!(router
+            .try_bind(&Url::new("/list/len"), Method::Get, ok)
+            .is_err());
+        assert!(router
+            .try_bind(&Url::new("/*rest/len"), Method::Put, ok)
+            .is_err());
+        assert!(router
+            .try_bind(&Url::new("/:name"), Method::Put, ok)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_mount() {
+        let mut admin = Router::new();
+        admin.bind_get(&Url::new("/"), |c| Ok(c.write_text("admin")?));
+        admin.bind_get(&Url::new("/users/:id"), |c| {
+            Ok(c.write_text(c.req.url.clone().get_param("id").unwrap())?)
+        });
+        admin.add_middleware(|c, next| {
+            c.res.add_header("X-Admin", "1");
+            next(c)
+        });
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/users/:id"), |c| Ok(c.write_text("user")?));
+        router.mount("/admin/", admin);
+
+        assert!(request(&router, Method::Get, "/admin").ends_with("admin"));
+        let res = request(&router, Method::Get, "/admin/users/7");
+        assert!(res.contains("X-Admin: 1") && res.ends_with("7"));
+        // the middlewares of the group do not run for the other routes
+        let res = request(&router, Method::Get, "/users/7");
+        assert!(!res.contains("X-Admin") && res.ends_with("user"));
+
+        let mut other = Router::new();
+        other.bind_get(&Url::new("/users/:name"), |_| Ok(()));
+        assert!(router.try_mount("/admin", other).is_err());
+    }
+
+    #[test]
+    fn test_head_and_options() {
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/:key"), |c| Ok(c.write_text("value")?));
+        router.bind_put(&Url::new("/:key"), |_| Ok(()));
+        router.allow_origin("http://app.example");
+
+        let res = request(&router, Method::Head, "/a");
+        assert!(res.starts_with("HTTP/1.1 200") && res.contains("Content-Length: 5"));
+        assert!(res.ends_with("\r\n\r\n"));
+
+        let res = request(&router, Method::Options, "/a");
+        assert!(res.starts_with("HTTP/1.1 204") && !res.contains("Content-Length"));
+        assert!(res.contains("Allow: GET, HEAD, PUT, OPTIONS"));
+        assert!(!res.contains("Access-Control"));
+        assert!(request(&router, Method::Patch, "/a").starts_with("HTTP/1.1 405"));
+
+        let mut req = HttpRequest {
+            method: Method::Options,
+            url: Url::new("/a"),
+            ..Default::default()
         };
-        // we should at least return the response headers.
-        if !ctx.has_written {
-            ctx.write_empty()?;
+        req.headers.put(headers::ORIGIN, "http://app.example");
+        req.headers
+            .put(headers::ACCESS_CONTROL_REQUEST_METHOD, "PUT");
+        let res = send(&router, req);
+        assert!(res.contains("Access-Control-Allow-Origin: http://app.example"));
+        assert!(res.contains("Access-Control-Allow-Methods: GET, HEAD, PUT, OPTIONS"));
+    }
+
+    #[test]
+    fn test_hold_worker() {
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/watch"), |c| {
+            if !c.hold_worker()? {
+                return Ok(());
+            }
+            Ok(c.write_text("held")?)
+        });
+        let config = ServerConfig {
+            max_held_workers: 1,
+            ..Default::default()
+        };
+        let held = Arc::new(AtomicUsize::new(0));
+        let watch = |held: &Arc<AtomicUsize>| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let req = HttpRequest {
+                url: Url::new("/watch"),
+                ..Default::default()
+            };
+            {
+                let mut ctx = Context::new(req, HttpResponse::default(), &mut stream);
+                ctx.limit(&config, held.clone());
+                let _ = router.route(&Url::new("/watch"), Method::Get, &mut ctx);
+            }
+            drop(stream);
+            let mut res = String::new();
+            client.read_to_string(&mut res).unwrap();
+            res
+        };
+        // the worker is given back once the request is served
+        assert!(watch(&held).ends_with("held"));
+        assert!(watch(&held).ends_with("held"));
+        held.fetch_add(1, Ordering::SeqCst);
+        let res = watch(&held);
+        assert!(res.starts_with("HTTP/1.1 503") && res.contains("Retry-After: 1"));
+        assert_eq!(held.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_failed_chunks() {
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/scan"), |c| {
+            c.start_chunks(headers::CONTENT_TEXT_HTML)?;
+            c.write_chunk(b"first")?;
+            Err(ServerError::IoError(io::Error::other("storage failed")))
+        });
+        let req = HttpRequest {
+            method: Method::Get,
+            url: Url::new("/scan"),
+            version: Version::V11,
+            ..Default::default()
+        };
+        let res = send(&router, req);
+        assert!(res.contains("Transfer-Encoding: chunked") && res.ends_with("5\r\nfirst\r\n"));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut router = Router::new();
+        router.bind_get(&Url::new("/write"), |c| {
+            c.res.add_header("X-Value", "a\r\nInjected: 1");
+            Ok(c.write_text("value")?)
+        });
+        router.bind_get(&Url::new("/leave"), |c| {
+            c.res.append_header("X-Value", "a\nb");
+            Ok(())
+        });
+        for url in ["/write", "/leave"] {
+            let res = request(&router, Method::Get, url);
+            assert!(res.starts_with("HTTP/1.1 500"), "{}", res);
+            assert!(res.contains("Content-Length: 0") && !res.contains("X-Value"));
         }
-        result
     }
 }
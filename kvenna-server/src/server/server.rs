@@ -1,63 +1,264 @@
 use std::{
+    io::BufReader,
     net::{TcpListener, TcpStream},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
 };
 
 use super::{
-    errors,
-    request::{self, Url},
-    router::HandleResult,
-    Context, HttpResponse, Router, ThreadPool,
+    errors::{self, ServerError},
+    headers,
+    request::{self, TimedStream, Url},
+    response,
+    router::{HandleResult, Next},
+    status,
+    version::Version,
+    Context, HttpResponse, Method, Router, ServerConfig, ThreadPool,
 };
 
+// ConnectionSlot releases the place of a connection, or of a held worker, in its count
+// once it is served, even if a handler panics
+pub(super) struct ConnectionSlot(pub(super) Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub struct Server {
     thread_pool: ThreadPool,
-    pub router: Arc<Mutex<Router>>,
+    config: Arc<ServerConfig>,
+    // routes are bound before running, so requests only take the read lock and
+    // long-running handlers do not block the others
+    pub router: Arc<RwLock<Router>>,
+    // the connections being served
+    connections: Arc<AtomicUsize>,
+    // the workers held by watches and subscriptions
+    held_workers: Arc<AtomicUsize>,
 }
 
 impl Server {
     pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    pub fn with_config(mut config: ServerConfig) -> Self {
+        // a connection waiting in the queue of the pool is not timed
+        config.max_connections = config.max_connections.min(config.workers);
         Self {
-            thread_pool: ThreadPool::new(300),
-            router: Arc::new(Mutex::new(Router::new())),
+            thread_pool: ThreadPool::new(config.workers),
+            config: Arc::new(config),
+            router: Arc::new(RwLock::new(Router::new())),
+            connections: Arc::new(AtomicUsize::new(0)),
+            held_workers: Arc::new(AtomicUsize::new(0)),
         }
     }
 
     pub fn bind_get<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind_get(url, handler);
+        self
+    }
+
+    pub fn bind_head<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
-        self.router.lock().unwrap().bind_get(url, handler);
+        self.router.write().unwrap().bind_head(url, handler);
         self
     }
 
     pub fn bind_put<F>(&mut self, url: &Url, handler: F) -> &mut Self
     where
-        F: Fn(&mut Context) -> HandleResult + Send + 'static,
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
     {
-        self.router.lock().unwrap().bind_put(url, handler);
+        self.router.write().unwrap().bind_put(url, handler);
         self
     }
 
-    fn handle_request(router: Arc<Mutex<Router>>, stream: &mut TcpStream) -> errors::Result<()> {
-        let req = request::parse_request(stream)?;
-        let res = HttpResponse::default();
-        let (url, method) = (req.url.clone(), req.method);
-        let mut ctx = Context::new(req, res, stream);
-        router.lock().unwrap().route(&url, method, &mut ctx)?;
+    pub fn bind_patch<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind_patch(url, handler);
+        self
+    }
+
+    pub fn bind_post<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind_post(url, handler);
+        self
+    }
+
+    pub fn bind_delete<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind_delete(url, handler);
+        self
+    }
+
+    pub fn bind_options<F>(&mut self, url: &Url, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind_options(url, handler);
+        self
+    }
+
+    pub fn allow_origin(&mut self, origin: &str) -> &mut Self {
+        self.router.write().unwrap().allow_origin(origin);
+        self
+    }
+
+    pub fn bind<F>(&mut self, url: &Url, method: Method, handler: F) -> &mut Self
+    where
+        F: Fn(&mut Context) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().bind(url, method, handler);
+        self
+    }
+
+    // mount the routes of a router built apart, like those of a feature, under the prefix
+    pub fn mount(&mut self, prefix: &str, router: Router) -> &mut Self {
+        self.router.write().unwrap().mount(prefix, router);
+        self
+    }
+
+    pub fn add_middleware<F>(&mut self, middleware: F) -> &mut Self
+    where
+        F: Fn(&mut Context, Next) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router.write().unwrap().add_middleware(middleware);
+        self
+    }
+
+    pub fn add_prefix_middleware<F>(&mut self, prefix: &str, middleware: F) -> &mut Self
+    where
+        F: Fn(&mut Context, Next) -> HandleResult + Send + Sync + 'static,
+    {
+        self.router
+            .write()
+            .unwrap()
+            .add_prefix_middleware(prefix, middleware);
+        self
+    }
+
+    // serve the requests of the connection one after the other, so that pipelined
+    // requests are answered in order, until either side closes it
+    fn handle_connection(
+        router: Arc<RwLock<Router>>,
+        config: &ServerConfig,
+        held_workers: Arc<AtomicUsize>,
+        stream: &mut TcpStream,
+    ) -> errors::Result<()> {
+        stream.set_write_timeout(Some(config.write_timeout))?;
+        let mut reader = BufReader::new(TimedStream::new(stream.try_clone()?));
+        let mut served = 0;
+        loop {
+            let req = match request::parse_request(&mut reader, config) {
+                Ok(req) => req,
+                Err(ServerError::ConnectionClosed) => return Ok(()),
+                Err(err @ ServerError::IoError(_)) => return Err(err),
+                // a malformed or slow request is the client's fault, it is answered
+                // but not logged
+                Err(err) => {
+                    stream.set_write_timeout(Some(config.write_timeout))?;
+                    return Self::reject(stream, &err);
+                }
+            };
+            served += 1;
+            let keep_alive = req.keep_alive() && served < config.max_requests;
+            let mut res = HttpResponse::default();
+            if !keep_alive {
+                res.add_header(headers::CONNECTION, "close");
+            } else if req.version == Version::V10 {
+                res.add_header(headers::CONNECTION, "keep-alive");
+            }
+            let (url, method) = (req.url.clone(), req.method);
+            let mut ctx = Context::new(req, res, stream);
+            ctx.keep_alive = keep_alive;
+            ctx.limit(config, held_workers.clone());
+            match router.read().unwrap().route(&url, method, &mut ctx) {
+                Ok(()) => {}
+                // the connection is broken
+                Err(err @ ServerError::IoError(_)) => return Err(err),
+                // the unmatched routes are answered with 404 or 405 already
+                Err(ServerError::RouteError(_) | ServerError::MethodNotAllowed(..)) => {}
+                Err(err) => eprintln!("{}", err),
+            }
+            if !ctx.keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    // answer a request refused while it was parsed, the connection is closed afterwards
+    fn reject(stream: &mut TcpStream, err: &ServerError) -> errors::Result<()> {
+        let status_code = match err {
+            ServerError::PayloadTooLarge(_) => status::PAYLOAD_TOO_LARGE,
+            ServerError::UriTooLong(_) => status::URI_TOO_LONG,
+            ServerError::PareRequestError => status::BAD_REQUEST,
+            ServerError::RequestTimeout => status::REQUEST_TIMEOUT,
+            ServerError::HeadersTooLarge(..) => status::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ServerError::TooManyConnections(_) => status::SERVICE_UNAVAILABLE,
+            _ => return Ok(()),
+        };
+        let mut res = HttpResponse::default();
+        let message = err.to_string();
+        if status_code == status::SERVICE_UNAVAILABLE {
+            res.add_header(headers::RETRY_AFTER, "1");
+        }
+        res.status(status_code)
+            .add_header(headers::CONTENT_LENGTH, &message.len().to_string())
+            .add_header(headers::CONNECTION, "close")
+            .body(message.into_bytes());
+        response::write_response(stream, &res)?;
         Ok(())
     }
 
+    // answer a connection beyond max_connections with 503 from the accepting thread, the
+    // short response fits in the socket buffer so the write does not wait for the client
+    fn refuse(stream: &mut TcpStream, config: &ServerConfig) {
+        let err = ServerError::TooManyConnections(config.max_connections);
+        let _ = stream
+            .set_write_timeout(Some(config.write_timeout))
+            .map_err(ServerError::from)
+            .and_then(|_| Self::reject(stream, &err));
+        eprintln!("{}", err);
+    }
+
     pub fn run(&mut self, addr: &str) {
-        let listner = TcpListener::bind(addr).unwrap();
-        for stream in listner.incoming() {
+        self.serve(TcpListener::bind(addr).unwrap());
+    }
+
+    // serve the connections accepted by the listener
+    pub fn serve(&mut self, listener: TcpListener) {
+        for stream in listener.incoming() {
             match stream {
                 Ok(mut stream) => {
-                    let router = self.router.clone();
+                    let connections = self.connections.clone();
+                    let max_connections = self.config.max_connections;
+                    if connections.fetch_add(1, Ordering::SeqCst) >= max_connections {
+                        connections.fetch_sub(1, Ordering::SeqCst);
+                        Self::refuse(&mut stream, &self.config);
+                        continue;
+                    }
+                    let (router, config) = (self.router.clone(), self.config.clone());
+                    let held_workers = self.held_workers.clone();
                     self.thread_pool.execute(move || {
-                        let _ = Self::handle_request(router, &mut stream).unwrap_or_else(|err| {
-                            eprintln!("{}", err);
-                        });
+                        let _served = ConnectionSlot(connections);
+                        Self::handle_connection(router, &config, held_workers, &mut stream)
+                            .unwrap_or_else(|err| {
+                                eprintln!("{}", err);
+                            });
                     });
                 }
                 Err(err) => {
@@ -67,3 +268,115 @@ impl Server {
         }
     }
 }
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::SocketAddr,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    // start a server with the config and the routes bound by bind on a port of its own
+    fn start<F>(config: ServerConfig, bind: F) -> SocketAddr
+    where
+        F: FnOnce(&mut Server),
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut server = Server::with_config(config);
+        bind(&mut server);
+        thread::spawn(move || server.serve(listener));
+        addr
+    }
+
+    // send the raw requests at once and read everything until the server closes
+    fn exchange(addr: SocketAddr, requests: &str) -> String {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(requests.as_bytes()).unwrap();
+        let mut res = String::new();
+        client.read_to_string(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_pipelining() {
+        let addr = start(ServerConfig::default(), |server| {
+            server.bind_get(&Url::new("/:name"), |c| {
+                let name = c.req.url.get_param("name").unwrap().to_owned();
+                Ok(c.write_text(&name)?)
+            });
+        });
+        let res = exchange(
+            addr,
+            "GET /first HTTP/1.1\r\n\r\nGET /second HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+        let first = res.find("\r\n\r\nfirst").unwrap();
+        let second = res.find("\r\n\r\nsecond").unwrap();
+        assert!(first < second);
+        assert_eq!(res.matches("HTTP/1.1 200").count(), 2);
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = ServerConfig {
+            workers: 2,
+            max_header_size: 256,
+            header_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let addr = start(config, |server| {
+            server.bind_get(&Url::new("/"), |c| Ok(c.write_text("ok")?));
+        });
+        // the headers have to arrive within header_timeout
+        let res = exchange(addr, "GET / HTTP/1.1\r\nHost: x\r\n");
+        assert!(res.starts_with("HTTP/1.1 408"), "{}", res);
+        let long = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(300));
+        let res = exchange(addr, &long);
+        assert!(res.starts_with("HTTP/1.1 431"), "{}", res);
+    }
+
+    #[test]
+    fn test_max_connections() {
+        // more connections than workers would wait for one without being timed
+        let config = ServerConfig {
+            workers: 1,
+            max_connections: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            Server::with_config(config.clone()).config.max_connections,
+            1
+        );
+
+        let addr = start(config, |server| {
+            server.bind_get(&Url::new("/"), |c| Ok(c.write_text("ok")?));
+        });
+        // the first connection takes the only worker while its request is incomplete
+        let mut first = TcpStream::connect(addr).unwrap();
+        first.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // the refused connection is closed at once, a request sent on it unread could
+        // make the client see a reset instead of the response
+        let res = exchange(addr, "");
+        assert!(res.starts_with("HTTP/1.1 503"), "{}", res);
+        assert!(res.contains("Retry-After: 1"));
+
+        // the place is given back once the first one is served
+        first.write_all(b"Connection: close\r\n\r\n").unwrap();
+        let mut res = String::new();
+        first.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+        let res = exchange(addr, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    }
+}
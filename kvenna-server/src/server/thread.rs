@@ -24,7 +24,10 @@ impl Worker {
         Self {
             id,
             job: thread::spawn(move || loop {
-                if let Ok(job) = receiver.lock().unwrap().recv() {
+                // release the lock before running the job, a guard living in the
+                // `if let` would keep the other workers waiting until the job ends
+                let job = receiver.lock().unwrap().recv();
+                if let Ok(job) = job {
                     println!("Worker {} received job", id);
                     job();
                 }
@@ -1,5 +1,6 @@
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Version {
+    V10,
     V11,
     V20,
     Unsupported,
@@ -8,6 +9,7 @@ pub enum Version {
 impl From<&str> for Version {
     fn from(value: &str) -> Self {
         match value {
+            "HTTP/1.0" => Version::V10,
             "HTTP/1.1" => Version::V11,
             "HTTP/2.0" => Version::V20,
             _ => Version::Unsupported,
@@ -15,9 +17,9 @@ impl From<&str> for Version {
     }
 }
 
-impl Into<String> for Version {
-    fn into(self) -> String {
-        match self {
+impl From<Version> for String {
+    fn from(value: Version) -> Self {
+        match value {
             Version::V20 => "HTTP/2.0".to_owned(),
             _ => "HTTP/1.1".to_owned(),
         }
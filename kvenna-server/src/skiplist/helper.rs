@@ -8,11 +8,11 @@ fn traverse_level(mut head: NonNull<SkipNode>) {
         let next = unsafe { head.as_ref().next };
         match next {
             None => {
-                println!("");
+                println!();
                 return;
             }
             Some(next) => {
-                let x = unsafe { next.as_ref().key.borrow_mut() };
+                let x = unsafe { &next.as_ref().key };
                 print!(" -> {}", x);
                 head = next;
             }
@@ -1,4 +1,5 @@
 pub mod helper;
+#[allow(clippy::module_inception)]
 pub mod skiplist;
 pub mod skipnode;
 
@@ -1,21 +1,21 @@
 #![allow(dead_code)]
 
 use std::{
-    cell::RefCell,
-    ops::{Deref, DerefMut},
+    marker::PhantomData,
     ptr::NonNull,
-    sync::Arc,
+    sync::{Arc, RwLock},
 };
 
 use rand::Rng;
 
 use super::{
-    skipnode::{KeyType, ValueType},
+    skipnode::{KeyType, Link, ValueType},
     SkipNode,
 };
 
 enum SearchResult {
-    InsertPath(Vec<NonNull<SkipNode>>),
+    // the last node before the key on every level, from the top, along with its rank
+    InsertPath(Vec<(NonNull<SkipNode>, usize)>),
     Exists(NonNull<SkipNode>),
 }
 
@@ -27,23 +27,30 @@ pub struct SkipList {
 }
 
 unsafe impl Send for SkipList {}
+// the methods taking &self only follow the links and read the values through their
+// locks, the links are changed by the methods taking &mut self only
+unsafe impl Sync for SkipList {}
 
 impl SkipList {
     fn top_level(&self) -> NonNull<SkipNode> {
         self.level_heads.last().copied().unwrap()
     }
 
-    fn grow_up(&mut self, path: Vec<NonNull<SkipNode>>, key: KeyType, value: ValueType) {
+    fn grow_up(&mut self, path: Vec<(NonNull<SkipNode>, usize)>, key: KeyType, value: ValueType) {
         let mut rng = rand::thread_rng();
         let mut cur;
         let mut last = None;
         let mut insert_up = true;
         let levels = self.levels();
+        let rank = path[levels - 1].1 + 1;
         let mut idx = levels as isize - 1;
         while insert_up && idx >= 0 {
-            let mut left = path[idx as usize];
+            let (mut left, left_rank) = path[idx as usize];
             cur = SkipNode::new(key.clone(), value.clone());
             unsafe {
+                // the new node takes over the part of the span beyond it
+                cur.as_mut().span = left.as_ref().span + left_rank + 1 - rank;
+                left.as_mut().span = rank - left_rank;
                 left.as_mut().instert_right(cur);
                 cur.as_mut().down = last;
             }
@@ -52,6 +59,12 @@ impl SkipList {
             idx -= 1;
             insert_up = rng.gen::<f64>() <= 0.5;
         }
+        // the levels above the tower now skip over one more entry
+        while idx >= 0 {
+            let (mut left, _) = path[idx as usize];
+            unsafe { left.as_mut().span += 1 };
+            idx -= 1;
+        }
 
         if insert_up {
             let mut sentinel = self.sentinel();
@@ -59,6 +72,8 @@ impl SkipList {
             cur = SkipNode::new(key, value);
             unsafe {
                 cur.as_mut().down = last;
+                cur.as_mut().span = self.size + 2 - rank;
+                sentinel.as_mut().span = rank;
                 sentinel.as_mut().down = Some(down_sentinel);
                 sentinel.as_mut().instert_right(cur)
             }
@@ -70,18 +85,19 @@ impl SkipList {
     fn search(&self, key: &str) -> SearchResult {
         let mut path = vec![];
         let mut p = self.top_level();
+        let mut rank = 0;
         for _ in 0..self.levels() {
             while let Some(next) = unsafe { p.as_ref().next } {
-                let node_key = unsafe { next.as_ref().key.borrow() };
-                let node_key: &str = node_key.deref();
+                let node_key = unsafe { next.as_ref().key.as_str() };
                 if node_key > key {
                     break;
                 } else if node_key == key {
                     return SearchResult::Exists(next);
                 }
+                rank += unsafe { p.as_ref().span };
                 p = next;
             }
-            path.push(p);
+            path.push((p, rank));
 
             if let Some(down) = unsafe { p.as_ref() }.down {
                 p = down;
@@ -89,6 +105,41 @@ impl SkipList {
         }
         SearchResult::InsertPath(path)
     }
+
+    // return the first node of the bottom level whose key is not less than key
+    fn lower_bound(&self, key: &str) -> Link {
+        let mut p = self.top_level();
+        loop {
+            while let Some(next) = unsafe { p.as_ref().next } {
+                if unsafe { next.as_ref().key.as_str() } >= key {
+                    break;
+                }
+                p = next;
+            }
+            match unsafe { p.as_ref().down } {
+                Some(down) => p = down,
+                None => return unsafe { p.as_ref().next },
+            }
+        }
+    }
+}
+
+// Iter walks the bottom level of a skiplist, yielding the entries in key order
+pub struct Iter<'a> {
+    next: Link,
+    _skiplist: PhantomData<&'a SkipList>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (String, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = unsafe { self.next?.as_ref() };
+        self.next = node.next;
+        let key = node.key.to_string();
+        let value = node.value.read().unwrap().clone().unwrap_or_default();
+        Some((key, value))
+    }
 }
 
 impl SkipList {
@@ -100,8 +151,8 @@ impl SkipList {
         let mut s = Self {
             level_heads: vec![],
             size: 0,
-            empty_key: Arc::new(RefCell::new(String::new())),
-            empty_value: Arc::new(RefCell::new(Some(Vec::new()))),
+            empty_key: Arc::new(String::new()),
+            empty_value: Arc::new(RwLock::new(Some(Vec::new()))),
         };
         s.level_heads.push(s.sentinel());
         s
@@ -116,21 +167,21 @@ impl SkipList {
     }
 
     fn update(mut node: NonNull<SkipNode>, new_val: Vec<u8>) {
-        let mut value_ref = unsafe { node.as_mut().value.borrow_mut() };
-        if let Some(value) = value_ref.deref_mut() {
-            value.clear();
-            value.extend_from_slice(&new_val);
-        }
+        let mut value_ref = unsafe { node.as_mut().value.write().unwrap() };
+        *value_ref = Some(new_val);
     }
 
     pub fn put(&mut self, key: &str, value: &[u8]) {
-        let result = self.search(&key);
+        let result = self.search(key);
         match result {
-            SearchResult::InsertPath(path) => self.grow_up(
-                path,
-                Arc::new(RefCell::new(key.to_string())),
-                Arc::new(RefCell::new(Some(value.to_vec()))),
-            ),
+            SearchResult::InsertPath(path) => {
+                self.grow_up(
+                    path,
+                    Arc::new(key.to_string()),
+                    Arc::new(RwLock::new(Some(value.to_vec()))),
+                );
+                self.size += 1;
+            }
             SearchResult::Exists(node) => Self::update(node, value.to_vec()),
         }
     }
@@ -140,13 +191,7 @@ impl SkipList {
     }
 
     pub fn get(&self, key: &str) -> Option<Vec<u8>> {
-        match self.search(&key) {
-            SearchResult::InsertPath(_) => None,
-            SearchResult::Exists(node) => {
-                let value = unsafe { node.as_ref().value.borrow().clone() };
-                value
-            }
-        }
+        self.with_value(key, |value| value.to_vec())
     }
 
     pub fn get_string(&self, key: &str) -> Option<String> {
@@ -154,22 +199,148 @@ impl SkipList {
             .map(|val| String::from_utf8_lossy(&val).to_string())
     }
 
-    pub fn del(&self, key: &str) -> Option<Vec<u8>> {
-        match self.search(&key) {
+    // borrow the stored value in place, so that callers only interested in
+    // part of it don't have to clone the whole vector
+    pub fn with_value<F, R>(&self, key: &str, f: F) -> Option<R>
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        match self.search(key) {
             SearchResult::InsertPath(_) => None,
             SearchResult::Exists(node) => {
-                let value = unsafe { node.as_ref().value.borrow().clone() };
-                let mut p = Some(node);
-                while let Some(mut node) = p {
-                    unsafe {
-                        node.as_mut().value.borrow_mut().take();
-                        p = node.as_ref().down
+                let value = unsafe { node.as_ref().value.read().unwrap() };
+                value.as_deref().map(f)
+            }
+        }
+    }
+
+    // mutate the stored value in place, return None if the key does not exist
+    pub fn update_with<F, R>(&mut self, key: &str, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut Vec<u8>) -> R,
+    {
+        match self.search(key) {
+            SearchResult::InsertPath(_) => None,
+            SearchResult::Exists(mut node) => {
+                let mut value = unsafe { node.as_mut().value.write().unwrap() };
+                value.as_mut().map(f)
+            }
+        }
+    }
+
+    // iterate over all the entries in key order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            next: unsafe { self.level_heads[0].as_ref().next },
+            _skiplist: PhantomData,
+        }
+    }
+
+    // iterate in key order over the entries whose key is not less than start
+    pub fn iter_from(&self, start: &str) -> Iter<'_> {
+        Iter {
+            next: self.lower_bound(start),
+            _skiplist: PhantomData,
+        }
+    }
+
+    // return the 0-based position of the key in key order
+    pub fn rank(&self, key: &str) -> Option<usize> {
+        let mut p = self.top_level();
+        let mut rank = 0;
+        loop {
+            while let Some(next) = unsafe { p.as_ref().next } {
+                if unsafe { next.as_ref().key.as_str() } > key {
+                    break;
+                }
+                rank += unsafe { p.as_ref().span };
+                p = next;
+            }
+            // the sentinels have an empty key too, but rank 0
+            if rank > 0 && unsafe { p.as_ref().key.as_str() } == key {
+                return Some(rank - 1);
+            }
+            p = unsafe { p.as_ref().down }?;
+        }
+    }
+
+    // iterate in key order over the entries from the 0-based position index
+    pub fn iter_at(&self, index: usize) -> Iter<'_> {
+        let target = index + 1;
+        let mut p = self.top_level();
+        let mut rank = 0;
+        loop {
+            while let Some(next) = unsafe { p.as_ref().next } {
+                let span = unsafe { p.as_ref().span };
+                if rank + span > target {
+                    break;
+                }
+                rank += span;
+                p = next;
+            }
+            if rank == target {
+                while let Some(down) = unsafe { p.as_ref().down } {
+                    p = down;
+                }
+                return Iter {
+                    next: Some(p),
+                    _skiplist: PhantomData,
+                };
+            }
+            match unsafe { p.as_ref().down } {
+                Some(down) => p = down,
+                None => {
+                    return Iter {
+                        next: None,
+                        _skiplist: PhantomData,
                     }
                 }
-                value
             }
         }
     }
+
+    pub fn del(&mut self, key: &str) -> Option<Vec<u8>> {
+        let SearchResult::Exists(node) = self.search(key) else {
+            return None;
+        };
+        let value = unsafe { node.as_ref().value.write().unwrap().take() };
+        // walk down from the top, unlinking the tower on the levels it stands on and
+        // shortening the spans that skip over it on the others
+        let mut p = self.top_level();
+        loop {
+            while let Some(next) = unsafe { p.as_ref().next } {
+                if unsafe { next.as_ref().key.as_str() } >= key {
+                    break;
+                }
+                p = next;
+            }
+            unsafe {
+                match p.as_ref().next {
+                    Some(next) if next.as_ref().key.as_str() == key => {
+                        let node = Box::from_raw(next.as_ptr());
+                        p.as_mut().next = node.next;
+                        p.as_mut().span += node.span - 1;
+                        if let Some(mut next) = node.next {
+                            next.as_mut().prev = Some(p);
+                        }
+                    }
+                    _ => p.as_mut().span -= 1,
+                }
+                match p.as_ref().down {
+                    Some(down) => p = down,
+                    None => break,
+                }
+            }
+        }
+        self.size -= 1;
+        value
+    }
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Drop for SkipList {
@@ -1,26 +1,33 @@
-use std::{cell::RefCell, ptr::NonNull, sync::Arc};
+use std::{
+    ptr::NonNull,
+    sync::{Arc, RwLock},
+};
 
 pub(super) type Link = Option<NonNull<SkipNode>>;
-pub(super) type KeyType = Arc<RefCell<String>>;
-pub(super) type ValueType = Arc<RefCell<Option<Vec<u8>>>>;
+// the nodes of a tower share their key and value, keys never change once inserted
+pub(super) type KeyType = Arc<String>;
+pub(super) type ValueType = Arc<RwLock<Option<Vec<u8>>>>;
 
-#[derive(Eq)]
 pub struct SkipNode {
     pub key: KeyType,
     pub value: ValueType,
     pub prev: Link,
     pub next: Link,
     pub down: Link,
+    // the number of entries between this node and the next one on its level, the
+    // entries being ranked from 1 after the sentinel, a last node spans to size + 1
+    pub span: usize,
 }
 
 impl SkipNode {
     pub(super) fn new(key: KeyType, value: ValueType) -> NonNull<SkipNode> {
         let n = Box::new(Self {
-            key: key,
+            key,
             value,
             prev: None,
             next: None,
             down: None,
+            span: 1,
         });
         let n_ptr = Box::into_raw(n);
         unsafe { NonNull::new_unchecked(n_ptr) }
@@ -38,23 +45,14 @@ impl SkipNode {
     }
 }
 
-impl Drop for SkipNode {
-    fn drop(&mut self) {
-        let key = self.key.borrow();
-        if !key.is_empty() {
-            println!("Dropping node {}", key);
-        } else {
-            println!("Dropping sentinel node");
-        }
-    }
-}
-
 impl PartialEq for SkipNode {
     fn eq(&self, other: &Self) -> bool {
         self.key == other.key
     }
 }
 
+impl Eq for SkipNode {}
+
 impl Ord for SkipNode {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.key.cmp(&other.key)